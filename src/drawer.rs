@@ -1,6 +1,7 @@
-use std::collections::HashMap;
+use super::turtle::{Arc, Turtles};
 use svg::node::element::path::Data;
 use svg::node::element::{Path, Rectangle, Text};
+use svg::node::Node;
 use svg::Document;
 
 #[derive(Debug, PartialEq)]
@@ -16,15 +17,23 @@ pub enum DrawCmd {
     Label(String),
     SetFontSize(f64),
     SetTurtle(i32),
-}
-
-struct Turtle {
-    x: f64,
-    y: f64,
-    color: String,
-    rotation: f64,
-    pendown: bool,
-    font_size: f64,
+    SetXY(f64, f64),
+    SetX(f64),
+    SetY(f64),
+    /// Points the turtle at a heading in degrees, clockwise from north.
+    SetHeading(f64),
+    /// Goes back to the center of the image, heading north.
+    Home,
+    /// An arc of `angle` degrees and some radius around the turtle, which
+    /// stays where it is.
+    Arc(f64, f64),
+    /// An arc that the turtle moves along, turning by `angle` degrees.
+    Arc2(f64, f64),
+    SetFillColor(String),
+    /// Starts a shape that follows the turtle, filled with the current fill
+    /// color once it ends.
+    BeginFill,
+    EndFill,
 }
 
 pub fn draw(destination: &str, mut cmds: Vec<DrawCmd>, img_width: u32, img_height: u32) {
@@ -37,103 +46,154 @@ pub fn draw(destination: &str, mut cmds: Vec<DrawCmd>, img_width: u32, img_heigh
         .set("width", img_width)
         .set("height", img_height);
 
-    let mut turtles = HashMap::new();
-
-    fn create_turtle(turtles: &mut HashMap<i32, Turtle>, index: i32) {
-        turtles.insert(
-            index,
-            Turtle {
-                x: 0.0,
-                y: 0.0,
-                color: "black".to_string(),
-                rotation: std::f64::consts::PI / 2.0,
-                pendown: true,
-                font_size: 12.0,
-            },
-        );
-    }
+    let mut turtles = Turtles::default();
 
-    create_turtle(&mut turtles, 1);
+    // Elements are only added to the document at the end, so that the shape
+    // being filled can go under the lines drawn while filling it. `fill` holds
+    // where its path goes among `nodes`, its color and the path so far.
+    let mut nodes: Vec<Box<dyn Node>> = vec![];
+    let mut fill: Option<(usize, String, Data)> = None;
 
-    let mut turtle = turtles.get_mut(&1).unwrap();
+    // The y axis of the image points down.
+    let point = |(x, y): (f64, f64)| (center_x as f64 + x, center_y as f64 - y);
 
-    let move_forward = |u: f64, turtle: &mut Turtle, mut document: Document| {
-        let dx = u * f64::cos(turtle.rotation);
-        let dy = u * -f64::sin(turtle.rotation);
-        let data = Data::new()
-            .move_to((center_x as f64 + turtle.x, center_y as f64 + turtle.y))
-            .line_by((dx, dy));
-        let path = Path::new().set("d", data).set("stroke", &turtle.color[..]);
-        if turtle.pendown {
-            document = document.add(path);
+    // SVG arcs cannot go all the way round, so they are drawn in pieces of at
+    // most half a circle.
+    let arc_to = |mut data: Data, arc: &Arc| {
+        let pieces = (arc.angle.abs() / 180.0).ceil().max(1.0);
+        let sweep = if arc.angle < 0.0 { 0 } else { 1 };
+        for i in 1..=pieces as usize {
+            let (x, y) = point(arc.at(arc.angle * i as f64 / pieces));
+            data = data.elliptical_arc_to((arc.radius, arc.radius, 0, 0, sweep, x, y));
         }
-        turtle.x += dx;
-        turtle.y += dy;
-        document
+        data
+    };
+    let arc_path = |arc: &Arc, color: &str| {
+        let data = arc_to(Data::new().move_to(point(arc.at(0.0))), arc);
+        Path::new()
+            .set("d", data)
+            .set("fill", "none")
+            .set("stroke", color)
+    };
+    let fill_path = |data: Data, color: &str| {
+        Path::new()
+            .set("d", data.close())
+            .set("fill", color)
+            .set("stroke", "none")
     };
 
+    // While filling, the turtle also traces the shape. Moves with the pen up
+    // start a new part of it, so that they do not add edges.
     for cmd in cmds {
+        let before = turtles.current().clone();
+        turtles.apply(&cmd);
+        let turtle = turtles.current();
+        let to = point((turtle.x, turtle.y));
         match cmd {
-            DrawCmd::Forward(u) => {
-                document = move_forward(u, turtle, document);
-            }
-            DrawCmd::Back(u) => {
-                document = move_forward(-u, turtle, document);
-            }
-            DrawCmd::LeftTurn(d) => {
-                turtle.rotation += d * std::f64::consts::PI / 180.0;
-            }
-            DrawCmd::RightTurn(d) => {
-                turtle.rotation -= d * std::f64::consts::PI / 180.0;
+            DrawCmd::Forward(_)
+            | DrawCmd::Back(_)
+            | DrawCmd::SetXY(..)
+            | DrawCmd::SetX(_)
+            | DrawCmd::SetY(_)
+            | DrawCmd::Home => {
+                if before.pendown {
+                    let data = Data::new()
+                        .move_to(point((before.x, before.y)))
+                        .line_by((turtle.x - before.x, before.y - turtle.y));
+                    let path = Path::new().set("d", data).set("stroke", &before.color[..]);
+                    nodes.push(Box::new(path));
+                }
+                fill = fill.map(|(index, color, data)| {
+                    let data = if before.pendown {
+                        data.line_to(to)
+                    } else {
+                        data.move_to(to)
+                    };
+                    (index, color, data)
+                });
             }
-            DrawCmd::SetColor(c) => {
-                turtle.color = c;
+            DrawCmd::Arc(angle, radius) => {
+                if before.pendown {
+                    // Going round more than once draws the same circle.
+                    let arc = before.arc(angle.clamp(-360.0, 360.0), radius);
+                    nodes.push(Box::new(arc_path(&arc, &before.color)));
+                    fill = fill.map(|(index, color, data)| {
+                        let data = arc_to(data.move_to(point(arc.at(0.0))), &arc);
+                        (index, color, data.move_to(to))
+                    });
+                }
             }
-            DrawCmd::PenUp => {
-                turtle.pendown = false;
+            DrawCmd::Arc2(angle, radius) => {
+                let arc = before.arc2(angle, radius).shortened();
+                if before.pendown {
+                    nodes.push(Box::new(arc_path(&arc, &before.color)));
+                }
+                fill = fill.map(|(index, color, data)| {
+                    let data = if before.pendown {
+                        arc_to(data, &arc)
+                    } else {
+                        data.move_to(to)
+                    };
+                    (index, color, data)
+                });
             }
-            DrawCmd::PenDown => {
-                turtle.pendown = true;
+            DrawCmd::BeginFill | DrawCmd::EndFill => {
+                // Beginning a new shape ends the one being filled.
+                if let Some((index, color, data)) = fill.take() {
+                    nodes.insert(index, Box::new(fill_path(data, &color)));
+                }
+                if matches!(cmd, DrawCmd::BeginFill) {
+                    let color = turtle.fill_color.clone();
+                    fill = Some((nodes.len(), color, Data::new().move_to(to)));
+                }
             }
             DrawCmd::Label(s) => {
-                document = document.add(
+                let (x, y) = to;
+                nodes.push(Box::new(
                     Text::new()
-                        .set("x", center_x as f64 + turtle.x)
-                        .set("y", center_y as f64 + turtle.y)
+                        .set("x", x)
+                        .set("y", y)
                         .set("font-size", turtle.font_size)
                         .set(
                             "transform",
-                            format!(
-                                "rotate({} {} {})",
-                                -turtle.rotation * 180.0 / std::f64::consts::PI,
-                                center_x as f64 + turtle.x,
-                                center_y as f64 + turtle.y
-                            ),
+                            format!("rotate({} {} {})", turtle.heading - 90.0, x, y),
                         )
-                        .add(svg::node::Text::new(&s)),
-                )
-            }
-            DrawCmd::SetFontSize(n) => {
-                turtle.font_size = n;
-            }
-            DrawCmd::ClearScreen => {
-                document = document.add(
-                    Rectangle::new()
-                        .set("x", 0)
-                        .set("y", 0)
-                        .set("width", img_width)
-                        .set("height", img_height)
-                        .set("fill", "white"),
-                )
-            }
-            DrawCmd::SetTurtle(idx) => {
-                if let None = turtles.get(&idx) {
-                    create_turtle(&mut turtles, idx);
-                }
-                turtle = turtles.get_mut(&idx).unwrap();
+                        .add(svg::node::Text::new(escape_text(&s))),
+                ))
             }
+            DrawCmd::ClearScreen => nodes.push(Box::new(
+                Rectangle::new()
+                    .set("x", 0)
+                    .set("y", 0)
+                    .set("width", img_width)
+                    .set("height", img_height)
+                    .set("fill", "white"),
+            )),
+            DrawCmd::LeftTurn(_)
+            | DrawCmd::RightTurn(_)
+            | DrawCmd::SetColor(_)
+            | DrawCmd::SetFillColor(_)
+            | DrawCmd::PenUp
+            | DrawCmd::PenDown
+            | DrawCmd::SetFontSize(_)
+            | DrawCmd::SetTurtle(_)
+            | DrawCmd::SetHeading(_) => {}
         }
     }
+    // A shape that is never ended is filled all the same.
+    if let Some((index, color, data)) = fill {
+        nodes.insert(index, Box::new(fill_path(data, &color)));
+    }
 
+    for node in nodes {
+        document = document.add(node);
+    }
     svg::save(destination, &document).unwrap();
 }
+
+/// Escapes the characters that have a meaning in the text of an SVG element.
+fn escape_text(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
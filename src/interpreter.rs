@@ -1,184 +1,776 @@
 mod builtins;
+mod error;
 mod value;
 
 use super::drawer::DrawCmd;
-use super::parser::{AstNode, Binop};
+use super::parser::{parse_instructions, AstNode, Binop, LoopKind, Span, Unop};
+use super::turtle::Turtles;
 use builtins::get_builtins;
+pub use error::{CallInfo, ControlFlow, ErrorKind, RuntimeError};
 use std::collections::HashMap;
+use std::io::{self, Write};
 use std::iter::zip;
+use std::{panic, thread};
 use value::*;
 
+/// Iterations allowed in each run of a loop that ends on a condition, so that
+/// `forever` and other runaway loops end with an error. Loops that count,
+/// `repeat` and `for`, are bounded by their inputs.
+const ITERATION_LIMIT: usize = 10_000_000;
+
+/// Calls that may be active at once, so that runaway recursion, through
+/// procedures, templates or `run`, ends with an error instead of overflowing
+/// the stack.
+const CALL_LIMIT: usize = 2_000;
+
+/// Stack of the thread that runs a program, enough for `CALL_LIMIT` nested
+/// calls even in debug builds, where frames are much larger.
+const STACK_SIZE: usize = CALL_LIMIT * 64 * 1024;
+
+#[derive(Default)]
+struct Frame {
+    variables: HashMap<String, Value>,
+    /// Result of the last `test` run in this frame.
+    test: Option<bool>,
+    /// Number of loops whose body is running in this frame, which `break` and
+    /// `continue` need.
+    loops: usize,
+}
+
 pub struct Interpreter {
     drawing: Vec<DrawCmd>,
-    environment: HashMap<String, Value>,
+    /// The turtles as the drawing leaves them, for the procedures that ask
+    /// where they are.
+    turtles: Turtles,
+    /// Where `print`, `show` and `type` write their text.
+    writer: Box<dyn Write>,
+    procedures: HashMap<String, LogoFn>,
+    /// Variable frames, with globals at the bottom and one frame per active
+    /// procedure call on top. Names are resolved dynamically, innermost first.
+    frames: Vec<Frame>,
+    call_stack: Vec<CallInfo>,
+    /// Location of the builtin call being evaluated.
+    call_site: Span,
+    iteration_limit: usize,
+    /// Number of calls being evaluated, to builtins such as `run` as well as
+    /// to procedures and templates.
+    depth: usize,
+    call_limit: usize,
+}
+
+/// Number of inputs taken by each builtin, and the most it accepts in
+/// parentheses, used by the parser to group calls.
+pub fn builtin_arities() -> HashMap<String, (usize, usize)> {
+    let mut arities = HashMap::new();
+    get_builtins().into_iter().for_each(|(names, f)| {
+        names.into_iter().for_each(|n| {
+            arities.insert(n.to_string(), f.arities());
+        });
+    });
+    arities
 }
 
 impl Interpreter {
     fn new() -> Self {
-        let mut environment = HashMap::new();
+        let mut procedures = HashMap::new();
         get_builtins().into_iter().for_each(|(names, f)| {
             names.into_iter().for_each(|n| {
-                environment.insert(n.to_string(), f.clone());
+                procedures.insert(n.to_string(), f.clone());
             });
         });
         Self {
             drawing: vec![],
-            environment,
+            turtles: Turtles::default(),
+            writer: Box::new(io::stdout()),
+            procedures,
+            frames: vec![Frame::default()],
+            call_stack: vec![],
+            call_site: Span::default(),
+            iteration_limit: ITERATION_LIMIT,
+            depth: 0,
+            call_limit: CALL_LIMIT,
         }
     }
 
-    fn eval_binop(v1: Value, op: &Binop, v2: Value) -> Value {
-        match op {
-            Binop::And => match (v1, v2) {
-                (Value::Bool(b1), Value::Bool(b2)) => Value::Bool(b1 && b2),
-                (_, _) => panic!("bad operand type for &&"),
+    fn lookup(&self, name: &str) -> Result<Value, Box<RuntimeError>> {
+        match self.frames.iter().rev().find_map(|f| f.variables.get(name)) {
+            Some(Value::Nothing) | None => {
+                Err(RuntimeError::new(ErrorKind::Unbound(name.to_string()), name, vec![]).into())
+            }
+            Some(v) => Ok(v.clone()),
+        }
+    }
+
+    /// Parses a list of instructions, or a word holding one, so that it can be
+    /// run by `procedure`. Procedures defined so far are known to the parser.
+    fn instructions(&self, procedure: &str, value: &Value) -> Result<AstNode, Box<RuntimeError>> {
+        let text = match value {
+            Value::List(list) => list
+                .iter()
+                .map(|v| v.to_readable())
+                .collect::<Vec<_>>()
+                .join(" "),
+            v => match v.as_word() {
+                Some(word) => word,
+                None => return Err(RuntimeError::type_mismatch(procedure, v).into()),
             },
-            Binop::Or => match (v1, v2) {
-                (Value::Bool(b1), Value::Bool(b2)) => Value::Bool(b1 || b2),
-                (_, _) => panic!("bad operand type for ||"),
+        };
+        let arities = self
+            .procedures
+            .iter()
+            .map(|(name, f)| (name.clone(), f.arities()))
+            .collect();
+        parse_instructions(&text, arities, self.call_site).map_err(|err| {
+            RuntimeError::new(
+                ErrorKind::Syntax(err.variant.message().to_string()),
+                procedure,
+                vec![value.clone()],
+            )
+            .into()
+        })
+    }
+
+    /// Adds `cmd` to the drawing and moves the turtles accordingly.
+    fn draw(&mut self, cmd: DrawCmd) {
+        self.turtles.apply(&cmd);
+        self.drawing.push(cmd);
+    }
+
+    /// Writes text for `procedure`, right away so that it shows up even when
+    /// no newline follows.
+    fn write(&mut self, procedure: &str, text: &str) -> Result<(), Box<RuntimeError>> {
+        self.writer
+            .write_all(text.as_bytes())
+            .and_then(|_| self.writer.flush())
+            .map_err(|err| {
+                RuntimeError::new(ErrorKind::Write(err.to_string()), procedure, vec![]).into()
+            })
+    }
+
+    /// Assigns to the innermost existing binding of `name`, or creates a global one.
+    fn set_variable(&mut self, name: String, value: Value) {
+        let mut frames = self.frames.iter_mut().rev();
+        let frame = match frames.find(|f| f.variables.contains_key(&name)) {
+            Some(frame) => frame,
+            None => &mut self.frames[0],
+        };
+        frame.variables.insert(name, value);
+    }
+
+    /// Binds `name` in the frame of the innermost procedure call.
+    fn bind_local(&mut self, name: String, value: Value) {
+        self.frames
+            .last_mut()
+            .unwrap()
+            .variables
+            .insert(name, value);
+    }
+
+    fn call_user_fn(
+        &mut self,
+        proc_name: &str,
+        params: Vec<String>,
+        body: &AstNode,
+        args: Vec<Value>,
+        call_site: &Span,
+    ) -> Result<Value, ControlFlow> {
+        let bindings: Vec<(String, Value)> = zip(params, args).collect();
+        self.call_stack.push(CallInfo {
+            proc_name: proc_name.to_string(),
+            args: bindings.clone(),
+            call_site: *call_site,
+        });
+        self.frames.push(Frame {
+            variables: bindings.into_iter().collect(),
+            ..Frame::default()
+        });
+        let ret = match self.eval_instructions(body) {
+            Ok(()) | Err(ControlFlow::Stop) => Ok(Value::Nothing),
+            Err(ControlFlow::Output(v)) => Ok(v),
+            Err(ControlFlow::Error(err)) => Err(err.within(&self.call_stack).into()),
+            Err(ControlFlow::Break | ControlFlow::Continue) => {
+                unreachable!("break and continue are only allowed inside a loop")
+            }
+        };
+        self.frames.pop();
+        self.call_stack.pop();
+        ret
+    }
+
+    /// Calls `f` under the name `name` after checking its number of inputs.
+    fn call(
+        &mut self,
+        name: &str,
+        f: LogoFn,
+        args: Vec<Value>,
+        call_site: &Span,
+    ) -> Result<Value, ControlFlow> {
+        let (expected, accepted) = match &f {
+            LogoFn::LangFn { arity, inputs, .. } => (*arity, inputs.contains(&args.len())),
+            LogoFn::UserFn { params, .. } => (params.len(), params.len() == args.len()),
+            LogoFn::Template { params, .. } => (
+                params.len(),
+                params.is_empty() || params.len() == args.len(),
+            ),
+        };
+        if !accepted {
+            let got = args.len();
+            return Err(RuntimeError::new(ErrorKind::Arity { expected, got }, name, args).into());
+        }
+        if self.depth == self.call_limit {
+            let kind = ErrorKind::CallLimit(self.call_limit);
+            return Err(RuntimeError::new(kind, name, vec![]).into());
+        }
+        self.depth += 1;
+        let ret = match f {
+            LogoFn::LangFn { function, .. } => {
+                let outer = std::mem::replace(&mut self.call_site, *call_site);
+                let ret = function(self, args);
+                self.call_site = outer;
+                ret
+            }
+            LogoFn::UserFn { params, body } => {
+                self.call_user_fn(name, params, &body, args, call_site)
+            }
+            LogoFn::Template { params, body } => self.call_template(params, &body, args),
+        };
+        self.depth -= 1;
+        ret
+    }
+
+    /// Evaluates the instructions of a template with its inputs bound to
+    /// `params`, or to the slots `?`, `?1`, `?2`... when there are none.
+    fn call_template(
+        &mut self,
+        params: Vec<String>,
+        body: &AstNode,
+        args: Vec<Value>,
+    ) -> Result<Value, ControlFlow> {
+        let mut variables = HashMap::new();
+        if params.is_empty() {
+            if let Some(first) = args.first() {
+                variables.insert("?".to_string(), first.clone());
+            }
+            for (i, arg) in args.into_iter().enumerate() {
+                variables.insert(format!("?{}", i + 1), arg);
+            }
+        } else {
+            variables.extend(zip(params, args));
+        }
+        self.frames.push(Frame {
+            variables,
+            ..Frame::default()
+        });
+        let ret = self.eval(body);
+        self.frames.pop();
+        ret
+    }
+
+    /// Turns a template given to `procedure` into something to call, along
+    /// with a name for it: the name of a procedure, a list of instructions
+    /// using the slots `?`, `?1`, `?2`... or a list of inputs followed by
+    /// instructions, as in `[[x] :x * 2]`.
+    fn template(
+        &self,
+        procedure: &str,
+        template: &Value,
+    ) -> Result<(String, LogoFn), Box<RuntimeError>> {
+        let lambda = match template {
+            Value::List(list) => match list.split_first() {
+                Some((Value::List(params), body)) => Some((params, body)),
+                _ => None,
             },
-            Binop::Less => match (v1, v2) {
-                (Value::Number(n1), Value::Number(n2)) => Value::Bool(n1 < n2),
-                (_, _) => panic!("bad operand type for <"),
+            _ => None,
+        };
+        let f = match (lambda, template.as_word()) {
+            (Some((params, body)), _) => LogoFn::Template {
+                params: params
+                    .iter()
+                    .map(|p| match p.as_word() {
+                        Some(word) => Ok(word.trim_start_matches(':').to_string()),
+                        None => Err(RuntimeError::type_mismatch(procedure, template).into()),
+                    })
+                    .collect::<Result<_, Box<_>>>()?,
+                body: self.instructions(procedure, &Value::List(body.to_vec()))?,
             },
-            Binop::LessEqual => match (v1, v2) {
-                (Value::Number(n1), Value::Number(n2)) => Value::Bool(n1 <= n2),
-                (_, _) => panic!("bad operand type for <="),
+            (None, Some(name)) => match self.procedures.get(&name) {
+                Some(f) => return Ok((name, f.clone())),
+                None => {
+                    return Err(RuntimeError::new(
+                        ErrorKind::UnknownProcedure(name.clone()),
+                        &name,
+                        vec![],
+                    )
+                    .into())
+                }
             },
-            Binop::Greater => match (v1, v2) {
-                (Value::Number(n1), Value::Number(n2)) => Value::Bool(n1 > n2),
-                (_, _) => panic!("bad operand type for >"),
+            (None, None) => LogoFn::Template {
+                params: vec![],
+                body: self.instructions(procedure, template)?,
             },
-            Binop::GreaterEqual => match (v1, v2) {
-                (Value::Number(n1), Value::Number(n2)) => Value::Bool(n1 >= n2),
-                (_, _) => panic!("bad operand type for >="),
+        };
+        Ok((template.to_string(), f))
+    }
+
+    /// Calls a template with `args`, from the builtin being evaluated.
+    fn invoke(
+        &mut self,
+        template: &(String, LogoFn),
+        args: Vec<Value>,
+    ) -> Result<Value, ControlFlow> {
+        let (name, f) = template;
+        let call_site = self.call_site;
+        self.call(name, f.clone(), args, &call_site)
+    }
+
+    fn binop_name(op: &Binop) -> &'static str {
+        match op {
+            Binop::And => "and",
+            Binop::Or => "or",
+            Binop::Less => "<",
+            Binop::LessEqual => "<=",
+            Binop::Greater => ">",
+            Binop::GreaterEqual => ">=",
+            Binop::EqualEqual => "=",
+            Binop::NotEqual => "<>",
+            Binop::Add => "+",
+            Binop::Sub => "-",
+            Binop::Mul => "*",
+            Binop::Div => "/",
+            Binop::Rem => "%",
+            Binop::Pow => "^",
+        }
+    }
+
+    /// Reports the operands of `op` that have the wrong type.
+    fn bad_operands(op: &Binop, v1: Value, v2: Value) -> Box<RuntimeError> {
+        let wrong = |v: &Value| match op {
+            Binop::And | Binop::Or => !matches!(v, Value::Bool(_)),
+            Binop::Less | Binop::LessEqual | Binop::Greater | Binop::GreaterEqual => true,
+            _ => v.as_number().is_none(),
+        };
+        let values = [v1, v2].into_iter().filter(wrong).collect();
+        RuntimeError::new(ErrorKind::TypeMismatch, Self::binop_name(op), values).into()
+    }
+
+    fn eval_binop(v1: Value, op: &Binop, v2: Value) -> Result<Value, Box<RuntimeError>> {
+        match op {
+            Binop::And => match (v1, v2) {
+                (Value::Bool(b1), Value::Bool(b2)) => Ok(Value::Bool(b1 && b2)),
+                (v1, v2) => Err(Self::bad_operands(op, v1, v2)),
+            },
+            Binop::Or => match (v1, v2) {
+                (Value::Bool(b1), Value::Bool(b2)) => Ok(Value::Bool(b1 || b2)),
+                (v1, v2) => Err(Self::bad_operands(op, v1, v2)),
+            },
+            Binop::Less | Binop::LessEqual | Binop::Greater | Binop::GreaterEqual => {
+                match v1.logo_cmp(&v2) {
+                    Some(ord) => Ok(Value::Bool(match op {
+                        Binop::Less => ord.is_lt(),
+                        Binop::LessEqual => ord.is_le(),
+                        Binop::Greater => ord.is_gt(),
+                        _ => ord.is_ge(),
+                    })),
+                    None => Err(Self::bad_operands(op, v1, v2)),
+                }
+            }
+            Binop::EqualEqual => Ok(Value::Bool(v1.logo_eq(&v2))),
+            Binop::NotEqual => Ok(Value::Bool(!v1.logo_eq(&v2))),
+            Binop::Add => match (v1.as_number(), v2.as_number()) {
+                (Some(b1), Some(b2)) => Ok(Value::Number(b1 + b2)),
+                _ => Err(Self::bad_operands(op, v1, v2)),
             },
-            Binop::EqualEqual => match (v1, v2) {
-                (Value::Number(n1), Value::Number(n2)) => Value::Bool(n1 == n2),
-                (_, _) => panic!("bad operand type for =="),
+            Binop::Sub => match (v1.as_number(), v2.as_number()) {
+                (Some(b1), Some(b2)) => Ok(Value::Number(b1 - b2)),
+                _ => Err(Self::bad_operands(op, v1, v2)),
             },
-            Binop::Add => match (v1, v2) {
-                (Value::Number(b1), Value::Number(b2)) => Value::Number(b1 + b2),
-                (_, _) => panic!("bad operand type for +"),
+            Binop::Mul => match (v1.as_number(), v2.as_number()) {
+                (Some(b1), Some(b2)) => Ok(Value::Number(b1 * b2)),
+                _ => Err(Self::bad_operands(op, v1, v2)),
             },
-            Binop::Sub => match (v1, v2) {
-                (Value::Number(b1), Value::Number(b2)) => Value::Number(b1 - b2),
-                (_, _) => panic!("bad operand type for -"),
+            Binop::Div => match (v1.as_number(), v2.as_number()) {
+                (Some(b1), Some(b2)) if b2 == 0.0 => Err(RuntimeError::new(
+                    ErrorKind::DivisionByZero,
+                    "/",
+                    vec![Value::Number(b1), Value::Number(b2)],
+                )
+                .into()),
+                (Some(b1), Some(b2)) => Ok(Value::Number(b1 / b2)),
+                _ => Err(Self::bad_operands(op, v1, v2)),
             },
-            Binop::Mul => match (v1, v2) {
-                (Value::Number(b1), Value::Number(b2)) => Value::Number(b1 * b2),
-                (_, _) => panic!("bad operand type for *"),
+            Binop::Rem => match (v1.as_number(), v2.as_number()) {
+                (Some(b1), Some(b2)) if b2 == 0.0 => Err(RuntimeError::new(
+                    ErrorKind::DivisionByZero,
+                    "%",
+                    vec![Value::Number(b1), Value::Number(b2)],
+                )
+                .into()),
+                (Some(b1), Some(b2)) => Ok(Value::Number(b1 % b2)),
+                _ => Err(Self::bad_operands(op, v1, v2)),
             },
-            Binop::Div => match (v1, v2) {
-                (Value::Number(b1), Value::Number(b2)) => Value::Number(b1 / b2),
-                (_, _) => panic!("bad operand type for /"),
+            Binop::Pow => match (v1.as_number(), v2.as_number()) {
+                (Some(b1), Some(b2)) => Ok(Value::Number(b1.powf(b2))),
+                _ => Err(Self::bad_operands(op, v1, v2)),
             },
         }
     }
 
-    fn eval(&mut self, node: &AstNode) -> Value {
-        match node {
-            AstNode::String(s) => Value::String(s.clone()),
-            AstNode::Number(n) => Value::Number(n.clone()),
-            AstNode::Variable(x) => match self.environment.get(&x[..]) {
-                Some(v) => (*v).clone(),
-                None => panic!("unbound variable {}", &x),
-            },
-            AstNode::List(elems) => {
-                Value::List(elems.iter().map(|e| self.eval(e)).collect::<Vec<_>>())
-            }
-            AstNode::Binop { lhs, op, rhs } => {
-                let v1 = self.eval(&lhs);
-                let v2 = self.eval(&rhs);
-                Self::eval_binop(v1, &op, v2)
+    fn loop_name(kind: &LoopKind) -> &'static str {
+        match kind {
+            LoopKind::Repeat(_) => "repeat",
+            LoopKind::While(_) => "while",
+            LoopKind::Until(_) => "until",
+            LoopKind::DoWhile(_) => "do.while",
+            LoopKind::DoUntil(_) => "do.until",
+            LoopKind::For { .. } => "for",
+            LoopKind::Forever => "forever",
+        }
+    }
+
+    fn loop_number(&mut self, node: &AstNode, name: &str) -> Result<f64, ControlFlow> {
+        let v = self.eval(node)?;
+        match v.as_number() {
+            Some(n) => Ok(n),
+            None => Err(RuntimeError::type_mismatch(name, &v).into()),
+        }
+    }
+
+    fn loop_condition(&mut self, node: &AstNode, name: &str) -> Result<bool, ControlFlow> {
+        match self.eval(node)? {
+            Value::Bool(b) => Ok(b),
+            v => Err(RuntimeError::type_mismatch(name, &v).into()),
+        }
+    }
+
+    /// Runs `body` for as long as `kind` requires, binding `repcount`, and the
+    /// control variable of `for`, in the current frame.
+    fn run_loop(&mut self, kind: &LoopKind, body: &AstNode) -> Result<Value, ControlFlow> {
+        let name = Self::loop_name(kind);
+        let times = match kind {
+            LoopKind::Repeat(n) => self.loop_number(n, name)? as i64,
+            _ => 0,
+        };
+        let (start, end, step) = match kind {
+            LoopKind::For {
+                start, end, step, ..
+            } => {
+                let start = self.loop_number(start, name)?;
+                let end = self.loop_number(end, name)?;
+                let step = match step {
+                    Some(step) => self.loop_number(step, name)?,
+                    None if start <= end => 1.0,
+                    None => -1.0,
+                };
+                (start, end, step)
             }
-            AstNode::Call { f, args } => {
-                let f = self.eval(&f);
-                let args: Vec<Value> = args.iter().map(|a| self.eval(&a)).collect();
-                match f {
-                    Value::Function(f) => match f {
-                        LogoFn::LangFn { arity, function } => {
-                            if args.len() == arity {
-                                function(self, args)
-                            } else {
-                                panic!("the number of args is different than the number of params")
-                            }
-                        }
-                        LogoFn::UserFn { params, body } => {
-                            if args.len() == params.len() {
-                                let saved: Vec<(String, Option<Value>)> = zip(params, args)
-                                    .map(|(x, v)| (x.clone(), self.environment.insert(x, v)))
-                                    .collect();
-                                self.eval(&body);
-                                saved.into_iter().for_each(|(x, v)| {
-                                    if let Some(v) = v {
-                                        self.environment.insert(x, v);
-                                    }
-                                });
-                                Value::Nothing
-                            } else {
-                                panic!("the number of args is different than the number of params")
-                            }
-                        }
-                    },
-                    _ => panic!("can only call functions"),
+            _ => (0.0, 0.0, 0.0),
+        };
+        let mut i: i64 = 0;
+        loop {
+            let more = match kind {
+                LoopKind::Repeat(_) => i < times,
+                LoopKind::While(c) => self.loop_condition(c, name)?,
+                LoopKind::Until(c) => !self.loop_condition(c, name)?,
+                LoopKind::DoWhile(c) => i == 0 || self.loop_condition(c, name)?,
+                LoopKind::DoUntil(c) => i == 0 || !self.loop_condition(c, name)?,
+                LoopKind::For { var, .. } => {
+                    let x = start + i as f64 * step;
+                    self.bind_local(var.clone(), Value::Number(x));
+                    if step < 0.0 {
+                        x >= end
+                    } else {
+                        x <= end
+                    }
                 }
+                LoopKind::Forever => true,
+            };
+            if !more {
+                return Ok(Value::Nothing);
             }
-            AstNode::If { condition, body } => {
-                let c = self.eval(condition);
-                match c {
-                    Value::Bool(true) => self.eval(body),
-                    Value::Bool(false) => Value::Nothing,
-                    _ => panic!("guard type is not boolean"),
-                }
+            let counted = matches!(kind, LoopKind::Repeat(_) | LoopKind::For { .. });
+            if !counted && i as usize == self.iteration_limit {
+                return Err(RuntimeError::new(
+                    ErrorKind::IterationLimit(self.iteration_limit),
+                    name,
+                    vec![],
+                )
+                .into());
             }
-            AstNode::Loop { repeat, body } => {
-                let r = self.eval(repeat);
-                let saved = self.environment.get("repcount").cloned();
-                if let Value::Number(n) = r {
-                    for i in 1..=(n as i32) {
-                        self.environment
-                            .insert("repcount".to_string(), Value::Number((i as f64).clone()));
-                        self.eval(body);
-                    }
-                } else {
-                    panic!("repeat is not a number")
-                }
-                if let Some(v) = saved {
-                    self.environment.insert("repcount".to_string(), v);
-                }
-                Value::Nothing
+            i += 1;
+            self.bind_local("repcount".to_string(), Value::Number(i as f64));
+            // Only the body can `break` or `continue`, not the count or the
+            // condition.
+            self.frames.last_mut().unwrap().loops += 1;
+            let ret = self.eval_instructions(body);
+            self.frames.last_mut().unwrap().loops -= 1;
+            match ret {
+                Ok(_) | Err(ControlFlow::Continue) => {}
+                Err(ControlFlow::Break) => return Ok(Value::Nothing),
+                Err(flow) => return Err(flow),
             }
+        }
+    }
+
+    /// Evaluates `node`, attributing errors without a location to it.
+    fn eval(&mut self, node: &AstNode) -> Result<Value, ControlFlow> {
+        self.eval_node(node).map_err(|flow| flow.at(node.span()))
+    }
+
+    // Each arm with more than a few temporaries lives in its own method: a
+    // procedure call goes through `eval_node` several times, and every byte of
+    // its frame limits how deep recursive procedures can go.
+    fn eval_node(&mut self, node: &AstNode) -> Result<Value, ControlFlow> {
+        match node {
+            AstNode::String(s, _) => Ok(Value::String(s.clone())),
+            AstNode::Number(n, _) => Ok(Value::Number(*n)),
+            AstNode::Variable(x, _) => Ok(self.lookup(x)?),
+            AstNode::List(elems, _) => self.eval_list(elems),
+            AstNode::Unop { op, operand, .. } => self.eval_unop(op, operand),
+            AstNode::Binop { lhs, op, rhs, .. } => self.eval_operands(lhs, op, rhs),
+            AstNode::Call { f, args, span } => self.eval_call(f, args, span),
+            AstNode::If {
+                condition,
+                body,
+                else_body,
+                ..
+            } => self.eval_if(condition, body, else_body.as_deref()),
+            AstNode::IfTest { expected, body, .. } => self.eval_iftest(*expected, body),
+            AstNode::Loop { kind, body, .. } => self.eval_loop(kind, body),
             AstNode::ProcDef {
                 proc_name,
                 params,
                 body,
-            } => {
-                let f = Value::Function(LogoFn::UserFn {
-                    params: params.clone(),
-                    body: (**body).clone(),
-                });
-                self.environment.insert((*proc_name).clone(), f);
-                Value::Nothing
+                ..
+            } => self.define(proc_name, params, body),
+            AstNode::Block(stmts, _) => self.eval_block(stmts),
+            AstNode::Comment(..) => Ok(Value::Nothing),
+        }
+    }
+
+    /// Evaluates the statements of a block, whose value is that of the last
+    /// one. The others must not output anything, as it would be lost.
+    fn eval_block(&mut self, stmts: &[AstNode]) -> Result<Value, ControlFlow> {
+        // Comments do not change the value of the block.
+        let mut stmts = stmts.iter().filter(|s| !matches!(s, AstNode::Comment(..)));
+        let Some(last) = stmts.next_back() else {
+            return Ok(Value::Nothing);
+        };
+        for stmt in stmts {
+            self.eval_instructions(stmt)?;
+        }
+        self.eval(last)
+    }
+
+    /// Evaluates instructions whose value, if they output one, would be lost,
+    /// which Logo reports rather than ignore.
+    fn eval_instructions(&mut self, node: &AstNode) -> Result<(), ControlFlow> {
+        match self.eval(node)? {
+            Value::Nothing => Ok(()),
+            value => {
+                let span = match node {
+                    AstNode::Block(stmts, _) => stmts
+                        .iter()
+                        .rfind(|s| !matches!(s, AstNode::Comment(..)))
+                        .map_or(node.span(), AstNode::span),
+                    _ => node.span(),
+                };
+                Err(RuntimeError::new(ErrorKind::Unused, "", vec![value])
+                    .at(span)
+                    .into())
             }
-            AstNode::Block(stmts) => {
-                let mut ret = Value::Nothing;
-                for stmt in stmts {
-                    ret = self.eval(stmt);
-                    if let Value::Return = ret {
-                        break;
-                    }
+        }
+    }
+
+    fn eval_list(&mut self, elems: &[AstNode]) -> Result<Value, ControlFlow> {
+        Ok(Value::List(
+            elems
+                .iter()
+                .map(|e| self.eval(e))
+                .collect::<Result<Vec<_>, _>>()?,
+        ))
+    }
+
+    fn eval_operands(
+        &mut self,
+        lhs: &AstNode,
+        op: &Binop,
+        rhs: &AstNode,
+    ) -> Result<Value, ControlFlow> {
+        let v1 = self.eval_input(lhs, Self::binop_name(op))?;
+        let v2 = self.eval_input(rhs, Self::binop_name(op))?;
+        Ok(Self::eval_binop(v1, op, v2)?)
+    }
+
+    fn eval_unop(&mut self, op: &Unop, operand: &AstNode) -> Result<Value, ControlFlow> {
+        let name = match op {
+            Unop::Neg => "-",
+            Unop::Not => "not",
+        };
+        let v = self.eval_input(operand, name)?;
+        match (op, v.as_number(), v) {
+            (Unop::Neg, Some(n), _) => Ok(Value::Number(-n)),
+            (Unop::Not, _, Value::Bool(b)) => Ok(Value::Bool(!b)),
+            (_, _, v) => Err(RuntimeError::type_mismatch(name, &v).into()),
+        }
+    }
+
+    fn eval_call(
+        &mut self,
+        f: &AstNode,
+        args: &[AstNode],
+        span: &Span,
+    ) -> Result<Value, ControlFlow> {
+        let (name, f) = self.callee(f)?;
+        let args: Vec<Value> = args
+            .iter()
+            .map(|a| self.eval_input(a, &name))
+            .collect::<Result<_, _>>()?;
+        self.call(&name, f, args, span)
+    }
+
+    /// Evaluates an input to `procedure`, which must output a value.
+    fn eval_input(&mut self, node: &AstNode, procedure: &str) -> Result<Value, ControlFlow> {
+        match self.eval(node)? {
+            Value::Nothing => {
+                let kind = ErrorKind::NoOutput(Self::producer(node).to_string());
+                Err(RuntimeError::new(kind, procedure, vec![])
+                    .at(node.span())
+                    .into())
+            }
+            v => Ok(v),
+        }
+    }
+
+    /// The name of the procedure run by `node`, for when it outputs nothing.
+    fn producer(node: &AstNode) -> &str {
+        match node {
+            AstNode::Call { f, .. } => Self::proc_name(f),
+            AstNode::If { else_body, .. } => match else_body {
+                Some(_) => "ifelse",
+                None => "if",
+            },
+            AstNode::IfTest { expected, .. } => match expected {
+                true => "iftrue",
+                false => "iffalse",
+            },
+            AstNode::Loop { kind, .. } => Self::loop_name(kind),
+            _ => "instruction",
+        }
+    }
+
+    /// The name of the procedure called by a call node.
+    fn proc_name(f: &AstNode) -> &str {
+        let AstNode::Variable(name, _) = f else {
+            unreachable!("the parser only calls procedures by name")
+        };
+        name
+    }
+
+    /// The procedure called by a call node, along with its name.
+    fn callee(&self, f: &AstNode) -> Result<(String, LogoFn), Box<RuntimeError>> {
+        let name = Self::proc_name(f);
+        match self.procedures.get(name) {
+            Some(f) => Ok((name.to_string(), f.clone())),
+            None => {
+                Err(
+                    RuntimeError::new(ErrorKind::UnknownProcedure(name.to_string()), name, vec![])
+                        .into(),
+                )
+            }
+        }
+    }
+
+    fn eval_if(
+        &mut self,
+        condition: &AstNode,
+        body: &AstNode,
+        else_body: Option<&AstNode>,
+    ) -> Result<Value, ControlFlow> {
+        match (self.eval(condition)?, else_body) {
+            (Value::Bool(true), _) => self.eval(body),
+            (Value::Bool(false), Some(else_body)) => self.eval(else_body),
+            (Value::Bool(false), None) => Ok(Value::Nothing),
+            (c, None) => Err(RuntimeError::type_mismatch("if", &c).into()),
+            (c, Some(_)) => Err(RuntimeError::type_mismatch("ifelse", &c).into()),
+        }
+    }
+
+    fn eval_iftest(&mut self, expected: bool, body: &AstNode) -> Result<Value, ControlFlow> {
+        match self.frames.last().unwrap().test {
+            Some(test) if test == expected => self.eval(body),
+            Some(_) => Ok(Value::Nothing),
+            None => Err(RuntimeError::new(
+                ErrorKind::NoTest,
+                if expected { "iftrue" } else { "iffalse" },
+                vec![],
+            )
+            .into()),
+        }
+    }
+
+    fn eval_loop(&mut self, kind: &LoopKind, body: &AstNode) -> Result<Value, ControlFlow> {
+        let mut locals = vec!["repcount".to_string()];
+        if let LoopKind::For { var, .. } = kind {
+            locals.push(var.clone());
+        }
+        let frame = self.frames.last_mut().unwrap();
+        let saved: Vec<_> = locals.iter().map(|n| frame.variables.remove(n)).collect();
+        let ret = self.run_loop(kind, body);
+        for (name, value) in zip(locals, saved) {
+            match value {
+                Some(v) => self.bind_local(name, v),
+                None => {
+                    let frame = self.frames.last_mut().unwrap();
+                    frame.variables.remove(&name);
                 }
-                ret
             }
         }
+        ret
+    }
+
+    /// Defines the procedure `proc_name`, unless it would replace a builtin.
+    fn define(
+        &mut self,
+        proc_name: &str,
+        params: &[String],
+        body: &AstNode,
+    ) -> Result<Value, ControlFlow> {
+        if let Some(LogoFn::LangFn { .. }) = self.procedures.get(proc_name) {
+            return Err(RuntimeError::new(
+                ErrorKind::Primitive(proc_name.to_string()),
+                "to",
+                vec![],
+            )
+            .into());
+        }
+        let f = LogoFn::UserFn {
+            params: params.to_vec(),
+            body: body.clone(),
+        };
+        self.procedures.insert(proc_name.to_string(), f);
+        Ok(Value::Nothing)
     }
 }
 
-pub fn evaluate(source: &AstNode) -> Vec<DrawCmd> {
-    let mut interpreter = Interpreter::new();
-    interpreter.eval(source);
-    interpreter.drawing
+/// Runs a program on a thread of its own, whose stack is large enough for the
+/// deepest recursion allowed.
+pub fn evaluate(source: &AstNode) -> Result<Vec<DrawCmd>, Box<RuntimeError>> {
+    thread::scope(|scope| {
+        let run = thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn_scoped(scope, || {
+                let mut interpreter = Interpreter::new();
+                match interpreter.eval_instructions(source) {
+                    Ok(()) => Ok(interpreter.drawing),
+                    Err(ControlFlow::Error(err)) => Err(err),
+                    // The primitives check that they are used inside a procedure or loop.
+                    Err(_) => unreachable!(),
+                }
+            })
+            .unwrap();
+        run.join()
+            .unwrap_or_else(|panic| panic::resume_unwind(panic))
+    })
 }
 
 #[cfg(test)]
@@ -192,7 +784,7 @@ mod tests {
         let ast = parse_logo_source(source);
         assert!(ast.is_ok());
         let ast = ast.unwrap();
-        assert_eq!(evaluate(&ast), vec![DrawCmd::Forward(3.0)])
+        assert_eq!(evaluate(&ast).unwrap(), vec![DrawCmd::Forward(3.0)])
     }
 
     #[test]
@@ -202,7 +794,7 @@ mod tests {
         assert!(ast.is_ok());
         let ast = ast.unwrap();
         assert_eq!(
-            evaluate(&ast),
+            evaluate(&ast).unwrap(),
             vec![
                 DrawCmd::Forward(1.0),
                 DrawCmd::Forward(1.0),
@@ -218,6 +810,938 @@ mod tests {
         let ast = parse_logo_source(source);
         assert!(ast.is_ok());
         let ast = ast.unwrap();
-        assert_eq!(evaluate(&ast), vec![])
+        assert_eq!(evaluate(&ast).unwrap(), vec![])
+    }
+
+    #[test]
+    fn procedure_with_output() {
+        let source = "to half :x output :x / 2 end fd half 10 + 2 fd 1 + (half 10)";
+        let ast = parse_logo_source(source);
+        assert!(ast.is_ok());
+        let ast = ast.unwrap();
+        assert_eq!(
+            evaluate(&ast).unwrap(),
+            vec![DrawCmd::Forward(6.0), DrawCmd::Forward(6.0)]
+        )
+    }
+
+    #[test]
+    fn global_and_local_variables() {
+        let source = "make \"x 1 name 2 \"y \
+            to f :y local \"x make \"x 10 localmake \"z 3 fd :x + :y + :z make \"y 5 end \
+            f 20 fd :x fd thing \"y";
+        let ast = parse_logo_source(source);
+        assert!(ast.is_ok());
+        let ast = ast.unwrap();
+        assert_eq!(
+            evaluate(&ast).unwrap(),
+            vec![
+                DrawCmd::Forward(33.0),
+                DrawCmd::Forward(1.0),
+                DrawCmd::Forward(2.0)
+            ]
+        );
+
+        for (source, message) in [
+            (
+                "make \"x 5 local \"x print :x",
+                "local can only be used inside a procedure",
+            ),
+            (
+                "localmake \"x 5",
+                "localmake can only be used inside a procedure",
+            ),
+        ] {
+            assert_eq!(error_message(source), message, "{}", source);
+        }
+        let source = "make \"x 5 print map [[y] local \"x localmake \"x :y :x] [1 2] print :x";
+        assert_eq!(globals_after(source)["x"], "5");
+    }
+
+    #[test]
+    fn make_updates_caller_binding() {
+        let source = "to inc make \"n :n + 1 end to f :n inc inc fd :n end f 1 make \"n 7 fd :n";
+        let ast = parse_logo_source(source);
+        assert!(ast.is_ok());
+        let ast = ast.unwrap();
+        assert_eq!(
+            evaluate(&ast).unwrap(),
+            vec![DrawCmd::Forward(3.0), DrawCmd::Forward(7.0)]
+        )
+    }
+
+    #[test]
+    fn parameters_do_not_leak() {
+        let source = "to f :x fd :x end f 1 fd :x";
+        let ast = parse_logo_source(source).unwrap();
+        let err = evaluate(&ast).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Unbound("x".to_string()));
+    }
+
+    #[test]
+    fn parameters_do_not_shadow_procedures() {
+        let source = "to f :fd :forward fd :fd + :forward end f 1 2";
+        let ast = parse_logo_source(source);
+        assert!(ast.is_ok());
+        let ast = ast.unwrap();
+        assert_eq!(evaluate(&ast).unwrap(), vec![DrawCmd::Forward(3.0)])
+    }
+
+    #[test]
+    fn recursion_restores_parameters() {
+        let source = "to f :n if :n > 0 [ f :n - 1 fd :n stop ] end f 3";
+        let ast = parse_logo_source(source);
+        assert!(ast.is_ok());
+        let ast = ast.unwrap();
+        assert_eq!(
+            evaluate(&ast).unwrap(),
+            vec![
+                DrawCmd::Forward(1.0),
+                DrawCmd::Forward(2.0),
+                DrawCmd::Forward(3.0)
+            ]
+        )
+    }
+
+    #[test]
+    fn runtime_errors() {
+        let run = |source: &str| evaluate(&parse_logo_source(source).unwrap()).unwrap_err();
+
+        let err = run("fd \"ten");
+        assert_eq!(err.kind, ErrorKind::TypeMismatch);
+        assert_eq!(err.procedure, "forward");
+        assert_eq!(err.to_string(), "forward doesn't like ten as input");
+
+        let err = run("fd 1 / (2 - 2)");
+        assert_eq!(err.kind, ErrorKind::DivisionByZero);
+
+        let err = run("setcolor pick []");
+        assert_eq!(err.kind, ErrorKind::EmptyList);
+
+        let err = run("jump 10");
+        assert_eq!(err.kind, ErrorKind::UnknownProcedure("jump".to_string()));
+        assert_eq!(err.to_string(), "I don't know how to jump");
+    }
+
+    #[test]
+    fn error_location() {
+        let source = "to f :x\n  fd :x\nend\nf \"ten";
+        let err = evaluate(&parse_logo_source(source).unwrap()).unwrap_err();
+        let span = err.span.unwrap();
+        assert_eq!((span.line, span.col), (2, 3));
+        assert!(err.render(source, "test.logo").contains("2 |   fd :x"));
+    }
+
+    #[test]
+    fn error_traceback() {
+        let source = "to g :n\n  fd :n / 0\nend\nto f :x :y\n  g :x + :y\nend\nf 1 2";
+        let err = evaluate(&parse_logo_source(source).unwrap()).unwrap_err();
+        let calls: Vec<String> = err.traceback.iter().map(|c| c.to_string()).collect();
+        assert_eq!(calls, vec!["g :n=3", "f :x=1 :y=2"]);
+        assert!(err.render(source, "test.logo").ends_with(
+            "in g :n=3\n  called from f :x=1 :y=2 at 5:3\n  called from toplevel at 7:1"
+        ));
+    }
+
+    #[test]
+    fn unused_values() {
+        for (source, message) in [
+            ("print 1 2", "You don't say what to do with 2"),
+            ("fd 10 20", "You don't say what to do with 20"),
+            ("3 + 4", "You don't say what to do with 7"),
+            ("to f output 1 end f", "You don't say what to do with 1"),
+            (
+                "repeat 2 [fd 1 [a b]]",
+                "You don't say what to do with [a b]",
+            ),
+            ("to f \"x fd 1 end f", "You don't say what to do with x"),
+        ] {
+            assert_eq!(error_message(source), message, "{}", source);
+        }
+        let err = evaluate(&parse_logo_source("fd 1\nfd 2 3").unwrap()).unwrap_err();
+        assert_eq!(err.span.map(|span| (span.line, span.col)), Some((2, 6)));
+    }
+
+    #[test]
+    fn missing_outputs() {
+        for (source, message) in [
+            ("to f end print f", "f didn't output to print"),
+            ("to f end print (list 1 f 2)", "f didn't output to list"),
+            ("to f end fd f", "f didn't output to fd"),
+            ("print if 1 = 2 [3]", "if didn't output to print"),
+            ("fd repeat 2 []", "repeat didn't output to fd"),
+        ] {
+            assert_eq!(error_message(source), message, "{}", source);
+        }
+        assert_eq!(
+            error_message("to f end print 1 + f"),
+            "f didn't output to +"
+        );
+        assert_eq!(
+            error_message("to f end print not f"),
+            "f didn't output to not"
+        );
+        let err = evaluate(&parse_logo_source("to f end\nfd 1 + f").unwrap()).unwrap_err();
+        assert_eq!(err.span.map(|span| (span.line, span.col)), Some((2, 8)));
+    }
+
+    #[test]
+    fn left_associative_arithmetic() {
+        let source = "fd 10 - 2 - 3 fd 8 / 4 / 2";
+        let ast = parse_logo_source(source);
+        assert!(ast.is_ok());
+        let ast = ast.unwrap();
+        assert_eq!(
+            evaluate(&ast).unwrap(),
+            vec![DrawCmd::Forward(5.0), DrawCmd::Forward(1.0)]
+        )
+    }
+
+    #[test]
+    fn unary_and_integer_arithmetic() {
+        let source = "make \"x 3 fd -:x fd 2 ^ 3 ^ 2 fd -2 ^ 2 fd 7 % 3 fd -7 % 3 \
+            fd quotient 7 2 fd quotient -7 2 fd remainder -7 3 fd modulo -7 3 fd modulo 7 -3 \
+            fd power 2 10 if not :x > 5 [fd 0]";
+        let ast = parse_logo_source(source);
+        assert!(ast.is_ok());
+        let ast = ast.unwrap();
+        assert_eq!(
+            evaluate(&ast).unwrap(),
+            [-3.0, 512.0, -4.0, 1.0, -1.0, 3.0, -3.0, -1.0, 2.0, -2.0, 1024.0, 0.0]
+                .into_iter()
+                .map(DrawCmd::Forward)
+                .collect::<Vec<_>>()
+        );
+
+        assert_eq!(
+            evaluate(&parse_logo_source("fd \"2 + \"-0.5 fd \"3").unwrap()).unwrap(),
+            vec![DrawCmd::Forward(1.5), DrawCmd::Forward(3.0)]
+        );
+
+        for (source, message) in [
+            ("fd 1 % 0", "%: division by zero"),
+            ("fd modulo 1 0", "modulo: division by zero"),
+            ("fd quotient \"a 2", "quotient doesn't like a as input"),
+            ("if not 1 [fd 1]", "not doesn't like 1 as input"),
+            ("fd -\"a", "- doesn't like a as input"),
+            ("fd \"inf + 1", "+ doesn't like inf as input"),
+            ("fd 1 - \"NaN", "- doesn't like NaN as input"),
+            ("fd -\"infinity", "- doesn't like infinity as input"),
+            ("fd \"+5", "forward doesn't like +5 as input"),
+            ("fd sqrt \"1e3", "sqrt doesn't like 1e3 as input"),
+        ] {
+            assert_eq!(error_message(source), message, "{}", source);
+        }
+    }
+
+    #[test]
+    fn math_functions() {
+        let source = "fd round 1000 * sin 30 fd round 100 * cos 60 fd round 100 * tan 45 \
+            fd round arctan 1 fd (arctan -1 0) fd round 1000 * radsin pi / 6 \
+            fd sqrt 16 fd abs -3 fd int -3.7 fd round 2.5 fd exp 0 fd ln 1 fd round log10 1000 \
+            fd round pi * 100 fd min 3 1 fd (max 1 5 2) fd (min 4)";
+        let ast = parse_logo_source(source);
+        assert!(ast.is_ok());
+        let ast = ast.unwrap();
+        assert_eq!(
+            evaluate(&ast).unwrap(),
+            [
+                500.0, 50.0, 100.0, 45.0, 180.0, 500.0, 4.0, 3.0, -3.0, 3.0, 1.0, 0.0, 3.0, 314.0,
+                1.0, 5.0, 4.0
+            ]
+            .into_iter()
+            .map(DrawCmd::Forward)
+            .collect::<Vec<_>>()
+        );
+
+        for (source, message) in [
+            ("fd sqrt -1", "sqrt doesn't like -1 as input"),
+            ("fd ln 0", "ln doesn't like 0 as input"),
+            ("fd sin \"a", "sin doesn't like a as input"),
+            ("fd (min)", "min expects 2 inputs, but got 0"),
+        ] {
+            assert_eq!(error_message(source), message, "{}", source);
+        }
+    }
+
+    #[test]
+    fn comparisons() {
+        let source = "make \"x 3 \
+            if :x <= 3 [ fd 1 ] if :x >= 4 [ fd 2 ] if :x <> 3 [ fd 3 ] if :x = 3 [ fd 4 ] \
+            if \"Red = \"red [ fd 5 ] if \"apple < \"banana [ fd 6 ] \
+            if [a [b c]] = [a [b c]] [ fd 7 ] if [a b] != [a c] [ fd 8 ] if [1 2] < [1 3] [ fd 9 ]";
+        let ast = parse_logo_source(source);
+        assert!(ast.is_ok());
+        let ast = ast.unwrap();
+        assert_eq!(
+            evaluate(&ast).unwrap(),
+            [1.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0]
+                .into_iter()
+                .map(DrawCmd::Forward)
+                .collect::<Vec<_>>()
+        );
+
+        let err = evaluate(&parse_logo_source("if [a] < 1 [ fd 1 ]").unwrap()).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::TypeMismatch);
+    }
+
+    #[test]
+    fn identifiers_with_keyword_prefixes() {
+        let source = "to draw-side2 :total fd :total end \
+            to ending? :step_size draw-side2 :step_size * 2 end ending? 3";
+        let ast = parse_logo_source(source);
+        assert!(ast.is_ok());
+        let ast = ast.unwrap();
+        assert_eq!(evaluate(&ast).unwrap(), vec![DrawCmd::Forward(6.0)])
+    }
+
+    #[test]
+    fn comments() {
+        let source = "; a square\nto side :n ; one side\n  fd :n rt 90 ; turn\nend\n\
+            repeat 2 [side 10 ; inside a block\n] setcolor pick [red ; not a color\n]\n; done";
+        let ast = parse_logo_source(source);
+        assert!(ast.is_ok());
+        let ast = ast.unwrap();
+        assert_eq!(
+            evaluate(&ast).unwrap(),
+            vec![
+                DrawCmd::Forward(10.0),
+                DrawCmd::RightTurn(90.0),
+                DrawCmd::Forward(10.0),
+                DrawCmd::RightTurn(90.0),
+                DrawCmd::SetColor("red".to_string()),
+            ]
+        )
+    }
+
+    #[test]
+    fn ifelse() {
+        let source = "to sign :x output ifelse :x < 0 [-1] [1] end \
+            ifelse 1 > 2 [fd 1] [fd 2] fd sign (0 - 5) fd 10 * sign 3";
+        let ast = parse_logo_source(source);
+        assert!(ast.is_ok());
+        let ast = ast.unwrap();
+        assert_eq!(
+            evaluate(&ast).unwrap(),
+            vec![
+                DrawCmd::Forward(2.0),
+                DrawCmd::Forward(-1.0),
+                DrawCmd::Forward(10.0),
+            ]
+        )
+    }
+
+    #[test]
+    fn test_iftrue_iffalse() {
+        let source = "to check :x test :x > 0 inner iftrue [fd 1] iff [bk 1] end \
+            to inner test \"false = \"true end \
+            check 5 check 0 test 1 = 1 ift [rt 90] iffalse [lt 90]";
+        let ast = parse_logo_source(source);
+        assert!(ast.is_ok());
+        let ast = ast.unwrap();
+        assert_eq!(
+            evaluate(&ast).unwrap(),
+            vec![
+                DrawCmd::Forward(1.0),
+                DrawCmd::Back(1.0),
+                DrawCmd::RightTurn(90.0),
+            ]
+        );
+
+        let err = evaluate(&parse_logo_source("iftrue [fd 1]").unwrap()).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::NoTest);
+        assert_eq!(err.to_string(), "iftrue without test");
+        assert_eq!(
+            error_message("ifelse 1 [fd 1] [fd 2]"),
+            "ifelse doesn't like 1 as input"
+        );
+    }
+
+    #[test]
+    fn loops() {
+        let source = "make \"i 0 while [:i < 2] [make \"i :i + 1 fd :repcount] \
+            until [:i = 0] [make \"i :i - 1 bk :i] \
+            do.while [rt 1] [1 = 0] do.until [lt :repcount] [:repcount = 2] \
+            for [i 1 6 2] [fd :i] for [j 2 1] [bk :j] fd :i";
+        let ast = parse_logo_source(source);
+        assert!(ast.is_ok());
+        let ast = ast.unwrap();
+        assert_eq!(
+            evaluate(&ast).unwrap(),
+            vec![
+                DrawCmd::Forward(1.0),
+                DrawCmd::Forward(2.0),
+                DrawCmd::Back(1.0),
+                DrawCmd::Back(0.0),
+                DrawCmd::RightTurn(1.0),
+                DrawCmd::LeftTurn(1.0),
+                DrawCmd::LeftTurn(2.0),
+                DrawCmd::Forward(1.0),
+                DrawCmd::Forward(3.0),
+                DrawCmd::Forward(5.0),
+                DrawCmd::Back(2.0),
+                DrawCmd::Back(1.0),
+                DrawCmd::Forward(0.0),
+            ]
+        );
+
+        let source = "repeat \"2 [fd 1] repeat last 23 [bk 1]";
+        assert_eq!(
+            evaluate(&parse_logo_source(source).unwrap()).unwrap(),
+            vec![
+                DrawCmd::Forward(1.0),
+                DrawCmd::Forward(1.0),
+                DrawCmd::Back(1.0),
+                DrawCmd::Back(1.0),
+                DrawCmd::Back(1.0),
+            ]
+        );
+        assert_eq!(
+            error_message("repeat \"two [fd 1]"),
+            "repeat doesn't like two as input"
+        );
+    }
+
+    #[test]
+    fn iteration_limit() {
+        let ast = parse_logo_source("forever [fd 1]").unwrap();
+        let mut interpreter = Interpreter::new();
+        interpreter.iteration_limit = 100;
+        let Err(ControlFlow::Error(err)) = interpreter.eval(&ast) else {
+            panic!("expected an error")
+        };
+        assert_eq!(err.kind, ErrorKind::IterationLimit(100));
+        assert_eq!(interpreter.drawing.len(), 100);
+
+        let source = "repeat 20 [repeat 20 [fd 1]] for [i 1 150] [fd 1] \
+            repeat 2 [make \"n 0 while [:n < 80] [make \"n :n + 1 fd 1]]";
+        let ast = parse_logo_source(source).unwrap();
+        let mut interpreter = Interpreter::new();
+        interpreter.iteration_limit = 100;
+        assert!(interpreter.eval(&ast).is_ok());
+        assert_eq!(interpreter.drawing.len(), 400 + 150 + 160);
+
+        assert_eq!(
+            error_message("while [1] [fd 1]"),
+            "while doesn't like 1 as input"
+        );
+    }
+
+    #[test]
+    fn call_limit() {
+        let ast = parse_logo_source("to f :n if :n > 0 [f :n - 1] end f 1000").unwrap();
+        assert!(evaluate(&ast).is_ok());
+
+        let source = "to f :n f :n + 1 end f 1";
+        let err = evaluate(&parse_logo_source(source).unwrap()).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::CallLimit(CALL_LIMIT));
+        let rendered = err.render(source, "test.logo");
+        let traceback: Vec<_> = rendered
+            .lines()
+            .skip_while(|l| !l.starts_with("in "))
+            .collect();
+        assert_eq!(
+            traceback,
+            [
+                "in f :n=2000",
+                "  called from f :n=1999 at 1:9",
+                "  called from f :n=1998 at 1:9",
+                "  called from f :n=1997 at 1:9",
+                "  called from f :n=1996 at 1:9",
+                "  called from f :n=1995 at 1:9",
+                "  ... 1990 more calls",
+                "  called from f :n=4 at 1:9",
+                "  called from f :n=3 at 1:9",
+                "  called from f :n=2 at 1:9",
+                "  called from f :n=1 at 1:9",
+                "  called from toplevel at 1:22",
+            ]
+        );
+
+        for source in [
+            "make \"r [run :r] run :r",
+            "make \"t [invoke :t 1] invoke :t 1",
+        ] {
+            let err = evaluate(&parse_logo_source(source).unwrap()).unwrap_err();
+            assert_eq!(err.kind, ErrorKind::CallLimit(CALL_LIMIT), "{}", source);
+        }
+    }
+
+    #[test]
+    fn unwinding() {
+        let source = "to walk repeat 10 [if :repcount > 2 [stop] fd :repcount] bk 1 end \
+            to find :n for [i 1 10] [if :i * :i > :n [output :i]] output 0 end \
+            walk fd find 20 \
+            repeat 5 [if :repcount = 2 [continue] if :repcount = 4 [break] rt :repcount] \
+            make \"i 0 forever [make \"i :i + 1 if :i > 2 [break]] lt :i";
+        let ast = parse_logo_source(source);
+        assert!(ast.is_ok());
+        let ast = ast.unwrap();
+        assert_eq!(
+            evaluate(&ast).unwrap(),
+            vec![
+                DrawCmd::Forward(1.0),
+                DrawCmd::Forward(2.0),
+                DrawCmd::Forward(5.0),
+                DrawCmd::RightTurn(1.0),
+                DrawCmd::RightTurn(3.0),
+                DrawCmd::LeftTurn(3.0),
+            ]
+        );
+
+        for (source, message) in [
+            ("stop", "stop can only be used inside a procedure"),
+            (
+                "fd 1 output 2",
+                "output can only be used inside a procedure",
+            ),
+            ("break", "break can only be used inside a loop"),
+            (
+                "to f continue end repeat 2 [f]",
+                "continue can only be used inside a loop",
+            ),
+            (
+                "while [break] [fd 1]",
+                "break can only be used inside a loop",
+            ),
+            (
+                "repeat break [fd 1]",
+                "break can only be used inside a loop",
+            ),
+            (
+                "do.while [fd 1] [break]",
+                "break can only be used inside a loop",
+            ),
+            (
+                "to f until [continue] [fd 1] end f",
+                "continue can only be used inside a loop",
+            ),
+        ] {
+            assert_eq!(error_message(source), message, "{}", source);
+        }
+    }
+
+    /// Runs `source` and returns its global variables, written as `show`
+    /// writes them.
+    fn globals_after(source: &str) -> HashMap<String, String> {
+        let mut interpreter = Interpreter::new();
+        assert!(interpreter
+            .eval(&parse_logo_source(source).unwrap())
+            .is_ok());
+        interpreter.frames[0]
+            .variables
+            .iter()
+            .map(|(name, value)| (name.clone(), value.to_string()))
+            .collect()
+    }
+
+    /// The message of the error that running `source` ends with.
+    fn error_message(source: &str) -> String {
+        evaluate(&parse_logo_source(source).unwrap())
+            .unwrap_err()
+            .to_string()
+    }
+
+    #[test]
+    fn list_primitives() {
+        let source = "make \"l [a b c] \
+            make \"a first :l make \"b butfirst :l make \"c last \"hello make \"d bl \"hello \
+            make \"e item 2 :l make \"f fput \"z :l make \"g lput \"z :l make \"h list :l \"d \
+            make \"i (sentence :l \"d [e [f]]) make \"j count :l make \"k count \"hello \
+            make \"m emptyp [] make \"n memberp \"B :l make \"o member? \"z \"hello \
+            make \"p reverse :l make \"q reverse \"abc make \"r first [[x y] z] make \"s (list) \
+            make \"t first 123 make \"u bf \"a make \"v fput \"a \"bc \
+            make \"w 1 + first 23 make \"x 2 * last 45 fd first 123";
+        let globals = globals_after(source);
+        for (name, value) in [
+            ("a", "a"),
+            ("b", "[b c]"),
+            ("c", "o"),
+            ("d", "hell"),
+            ("e", "b"),
+            ("f", "[z a b c]"),
+            ("g", "[a b c z]"),
+            ("h", "[[a b c] d]"),
+            ("i", "[a b c d e [f]]"),
+            ("j", "3"),
+            ("k", "5"),
+            ("m", "true"),
+            ("n", "true"),
+            ("o", "false"),
+            ("p", "[c b a]"),
+            ("q", "cba"),
+            ("r", "[x y]"),
+            ("s", "[]"),
+            ("t", "1"),
+            ("u", ""),
+            ("v", "abc"),
+            ("w", "3"),
+            ("x", "10"),
+        ] {
+            assert_eq!(globals[name], value, "{}", name);
+        }
+        assert_eq!(
+            evaluate(&parse_logo_source(source).unwrap()).unwrap(),
+            vec![DrawCmd::Forward(1.0)]
+        );
+
+        for (source, message) in [
+            ("make \"x first []", "first of an empty list"),
+            ("make \"x butlast bl \"a", "butlast of an empty list"),
+            ("make \"x item 4 [a b c]", "item doesn't like 4 as input"),
+        ] {
+            assert_eq!(error_message(source), message, "{}", source);
+        }
+    }
+
+    #[test]
+    fn numbers_in_lists() {
+        let source = "make \"points [[0 0] [10 -20]] \
+            fd 1 + first [-3] fd first last :points fd count :points rt item 2 item 2 :points \
+            if [1 [2 3]] = [1 [2 3]] [fd 1]";
+        let ast = parse_logo_source(source);
+        assert!(ast.is_ok());
+        let ast = ast.unwrap();
+        assert_eq!(
+            evaluate(&ast).unwrap(),
+            vec![
+                DrawCmd::Forward(-2.0),
+                DrawCmd::Forward(10.0),
+                DrawCmd::Forward(2.0),
+                DrawCmd::RightTurn(-20.0),
+                DrawCmd::Forward(1.0),
+            ]
+        )
+    }
+
+    #[test]
+    fn run_instruction_lists() {
+        let source = "make \"side [fd 10 rt 90] run :side \
+            to twice :cmds run :cmds run :cmds end twice [fd 1] \
+            repeat 2 :side make \"cond [:n < 2] make \"n 0 while :cond [make \"n :n + 1 fd :n] \
+            to f output first runresult [1 + 2] end fd f \
+            to g run [if 1 = 1 [stop]] fd 100 end g \
+            fd run \"f fd count runresult [make \"x 1]";
+        let ast = parse_logo_source(source);
+        assert!(ast.is_ok());
+        let ast = ast.unwrap();
+        assert_eq!(
+            evaluate(&ast).unwrap(),
+            vec![
+                DrawCmd::Forward(10.0),
+                DrawCmd::RightTurn(90.0),
+                DrawCmd::Forward(1.0),
+                DrawCmd::Forward(1.0),
+                DrawCmd::Forward(10.0),
+                DrawCmd::RightTurn(90.0),
+                DrawCmd::Forward(10.0),
+                DrawCmd::RightTurn(90.0),
+                DrawCmd::Forward(1.0),
+                DrawCmd::Forward(2.0),
+                DrawCmd::Forward(3.0),
+                DrawCmd::Forward(3.0),
+                DrawCmd::Forward(0.0),
+            ]
+        );
+
+        for (source, message) in [
+            ("run [fd]", "run: not enough inputs to fd"),
+            (
+                "make \"body [fd \"x] repeat 2 :body",
+                "forward doesn't like x as input",
+            ),
+        ] {
+            assert_eq!(error_message(source), message, "{}", source);
+        }
+        let source = "fd 1\nrun [fd []]";
+        let err = evaluate(&parse_logo_source(source).unwrap()).unwrap_err();
+        assert_eq!(err.span.map(|span| span.line), Some(2));
+    }
+
+    #[test]
+    fn templates() {
+        let source = "to sq :x output :x * :x end \
+            make \"a map [? * 2] [1 2 3] make \"b map [[x] :x + 1] [1 2] make \"c map \"sq [1 2 3] \
+            make \"d (map [?1 + ?2] [1 2] [10 20]) make \"e filter [? > 1] [1 2 3] \
+            make \"f reduce \"max [3 7 2] make \"g reduce [?1 - ?2] [10 4 1] \
+            make \"h apply \"quotient [7 2] make \"i (invoke [[x y] :x * :y] 3 4) \
+            make \"j filter [? = \"a] \"banana make \"k 0 foreach [1 2 3] [make \"k :k + ?]";
+        let globals = globals_after(source);
+        for (name, value) in [
+            ("a", "[2 4 6]"),
+            ("b", "[2 3]"),
+            ("c", "[1 4 9]"),
+            ("d", "[11 22]"),
+            ("e", "[2 3]"),
+            ("f", "7"),
+            ("g", "7"),
+            ("h", "3"),
+            ("i", "12"),
+            ("j", "aaa"),
+            ("k", "6"),
+        ] {
+            assert_eq!(globals[name], value, "{}", name);
+        }
+
+        let source = "foreach [10 20] [fd ?] (foreach [1 2] [90 45] [[d a] fd :d rt :a])";
+        assert_eq!(
+            evaluate(&parse_logo_source(source).unwrap()).unwrap(),
+            vec![
+                DrawCmd::Forward(10.0),
+                DrawCmd::Forward(20.0),
+                DrawCmd::Forward(1.0),
+                DrawCmd::RightTurn(90.0),
+                DrawCmd::Forward(2.0),
+                DrawCmd::RightTurn(45.0),
+            ]
+        );
+
+        for (source, message) in [
+            ("make \"x map [fd ?] [1]", "[fd ?] didn't output to map"),
+            (
+                "make \"x (map [?] [1 2] [1])",
+                "map doesn't like [1] as input",
+            ),
+            ("make \"x filter [?] [1]", "filter doesn't like 1 as input"),
+            ("make \"x reduce \"max []", "reduce of an empty list"),
+            (
+                "invoke [[x y] :x] 1",
+                "[[x y] :x] expects 2 inputs, but got 1",
+            ),
+            ("foreach [1] \"nothing", "I don't know how to nothing"),
+        ] {
+            assert_eq!(error_message(source), message, "{}", source);
+        }
+    }
+
+    #[test]
+    fn word_primitives() {
+        let source = "make \"a word \"abc 12 make \"b (word \"x \"y \"z) make \"c char 65 \
+            make \"d ascii \"a make \"e uppercase \"Hello make \"f lowercase \"HeLLo \
+            make \"g substring \"turtle 2 3 make \"h substring \"abc 4 0 make \"i wordp 12 \
+            make \"j word? [a] make \"k runresult [word \"|a b| \"c\\ d]";
+        let globals = globals_after(source);
+        for (name, value) in [
+            ("a", "abc12"),
+            ("b", "xyz"),
+            ("c", "A"),
+            ("d", "97"),
+            ("e", "HELLO"),
+            ("f", "hello"),
+            ("g", "urt"),
+            ("h", ""),
+            ("i", "true"),
+            ("j", "false"),
+            ("k", "[a bc d]"),
+        ] {
+            assert_eq!(globals[name], value, "{}", name);
+        }
+
+        let source = "label \"|Hello, world!| label 42 run [label \"Hi\\ there] \
+            fd word 1 2 make \"x \"5 fd :x";
+        assert_eq!(
+            evaluate(&parse_logo_source(source).unwrap()).unwrap(),
+            vec![
+                DrawCmd::Label("Hello, world!".to_string()),
+                DrawCmd::Label("42".to_string()),
+                DrawCmd::Label("Hi there".to_string()),
+                DrawCmd::Forward(12.0),
+                DrawCmd::Forward(5.0),
+            ]
+        );
+
+        for (source, message) in [
+            ("make \"x word \"a [b]", "word doesn't like [b] as input"),
+            ("make \"x ascii \"ab", "ascii doesn't like ab as input"),
+            (
+                "make \"x substring \"abc 2 5",
+                "substring doesn't like 5 as input",
+            ),
+            ("label [a]", "label doesn't like [a] as input"),
+            ("make \"x char -1", "char doesn't like -1 as input"),
+            ("make \"x char 65.5", "char doesn't like 65.5 as input"),
+        ] {
+            assert_eq!(error_message(source), message, "{}", source);
+        }
+    }
+
+    #[test]
+    fn turtle_queries() {
+        let source =
+            "fd 100 rt 90 fd 50 make \"a pos make \"b xcor make \"c ycor make \"d heading \
+            pu make \"e pendownp pd make \"f pendown? setcolor \"red make \"g pencolor \
+            make \"h towards [50 0] make \"i towards [0 100] make \"j distance [80 140] \
+            lt 450 make \"k heading setturtle 2 make \"l pos setturtle 1 make \"m pos";
+        let globals = globals_after(source);
+        for (name, value) in [
+            ("a", "[50 100]"),
+            ("b", "50"),
+            ("c", "100"),
+            ("d", "90"),
+            ("e", "false"),
+            ("f", "true"),
+            ("g", "red"),
+            ("h", "180"),
+            ("i", "270"),
+            ("j", "50"),
+            ("k", "0"),
+            ("l", "[0 0]"),
+            ("m", "[50 100]"),
+        ] {
+            assert_eq!(globals[name], value, "{}", name);
+        }
+
+        assert_eq!(
+            error_message("make \"x towards [1]"),
+            "towards doesn't like [1] as input"
+        );
+    }
+
+    #[test]
+    fn absolute_positioning() {
+        let source = "setxy 10 -20 make \"a pos setpos [30 40] setx -5 sety 6 make \"b pos \
+            seth 45 make \"c heading setheading -90 make \"d heading home make \"e list pos heading";
+        assert_eq!(
+            evaluate(&parse_logo_source(source).unwrap()).unwrap(),
+            vec![
+                DrawCmd::SetXY(10.0, -20.0),
+                DrawCmd::SetXY(30.0, 40.0),
+                DrawCmd::SetX(-5.0),
+                DrawCmd::SetY(6.0),
+                DrawCmd::SetHeading(45.0),
+                DrawCmd::SetHeading(-90.0),
+                DrawCmd::Home,
+            ]
+        );
+        let globals = globals_after(source);
+        for (name, value) in [
+            ("a", "[10 -20]"),
+            ("b", "[-5 6]"),
+            ("c", "45"),
+            ("d", "270"),
+            ("e", "[[0 0] 0]"),
+        ] {
+            assert_eq!(globals[name], value, "{}", name);
+        }
+
+        assert_eq!(
+            error_message("setpos [1 a]"),
+            "setpos doesn't like [1 a] as input"
+        );
+    }
+
+    #[test]
+    fn arcs() {
+        let source = "arc2 90 10 make \"a list pos heading arc2 -90 10 make \"b list pos heading \
+            arc 270 5 circle 5 make \"c list pos heading";
+        assert_eq!(
+            evaluate(&parse_logo_source(source).unwrap()).unwrap(),
+            vec![
+                DrawCmd::Arc2(90.0, 10.0),
+                DrawCmd::Arc2(-90.0, 10.0),
+                DrawCmd::Arc(270.0, 5.0),
+                DrawCmd::Arc(360.0, 5.0),
+            ]
+        );
+        let globals = globals_after(source);
+        for (name, value) in [
+            ("a", "[[10 10] 90]"),
+            ("b", "[[20 20] 0]"),
+            ("c", "[[20 20] 0]"),
+        ] {
+            assert_eq!(globals[name], value, "{}", name);
+        }
+
+        let globals = globals_after("arc2 -450 10 make \"d list pos heading");
+        assert_eq!(globals["d"], "[[-10 10] 270]");
+
+        for (source, message) in [
+            ("circle -1", "circle doesn't like -1 as input"),
+            ("arc 10 ^ 400 10", "arc doesn't like inf as input"),
+            ("arc2 90 10 ^ 400", "arc2 doesn't like inf as input"),
+        ] {
+            assert_eq!(error_message(source), message, "{}", source);
+        }
+    }
+
+    #[test]
+    fn fills() {
+        let source = "setfillcolor \"blue beginfill fd 10 rt 90 fd 10 endfill \
+            filled \"red [repeat 2 [fd 5 rt 90] pu fd 1 pd] \
+            to shape filled \"green [circle 3 stop] fd 100 end shape";
+        let ast = parse_logo_source(source).unwrap();
+        let mut interpreter = Interpreter::new();
+        assert!(interpreter.eval(&ast).is_ok());
+        assert_eq!(
+            interpreter.drawing,
+            vec![
+                DrawCmd::SetFillColor("blue".to_string()),
+                DrawCmd::BeginFill,
+                DrawCmd::Forward(10.0),
+                DrawCmd::RightTurn(90.0),
+                DrawCmd::Forward(10.0),
+                DrawCmd::EndFill,
+                DrawCmd::SetFillColor("red".to_string()),
+                DrawCmd::BeginFill,
+                DrawCmd::Forward(5.0),
+                DrawCmd::RightTurn(90.0),
+                DrawCmd::Forward(5.0),
+                DrawCmd::RightTurn(90.0),
+                DrawCmd::PenUp,
+                DrawCmd::Forward(1.0),
+                DrawCmd::PenDown,
+                DrawCmd::EndFill,
+                DrawCmd::SetFillColor("blue".to_string()),
+                DrawCmd::SetFillColor("green".to_string()),
+                DrawCmd::BeginFill,
+                DrawCmd::Arc(360.0, 3.0),
+                DrawCmd::EndFill,
+                DrawCmd::SetFillColor("blue".to_string()),
+            ]
+        );
+
+        assert_eq!(
+            error_message("filled 1 [fd 1]"),
+            "filled doesn't like 1 as input"
+        );
+    }
+
+    /// A writer whose text can still be read once given to the interpreter.
+    #[derive(Clone, Default)]
+    struct Capture(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+
+    impl Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn printing() {
+        let source = "print 1 + 2 print [a [b c] d] show [a [b c] d] show \"word \
+            (print \"a [b] 3) type \"x type [y z] (type \"u [v] 1) print [] \
+            print form 3.14159 8 2 fd 1 show form 2 0 0";
+        let ast = parse_logo_source(source).unwrap();
+        let capture = Capture::default();
+        let mut interpreter = Interpreter::new();
+        interpreter.writer = Box::new(capture.clone());
+        assert!(interpreter.eval(&ast).is_ok());
+        assert_eq!(
+            String::from_utf8(capture.0.borrow().clone()).unwrap(),
+            "3\na [b c] d\n[a [b c] d]\nword\na b 3\nxy zuv1\n    3.14\n2\n"
+        );
+        assert_eq!(interpreter.drawing, vec![DrawCmd::Forward(1.0)]);
+
+        for (source, message) in [
+            ("print form 1 -2 0", "form doesn't like -2 as input"),
+            ("print form 1 70000 2", "form doesn't like 70000 as input"),
+            ("print form 1 5 1001", "form doesn't like 1001 as input"),
+        ] {
+            assert_eq!(error_message(source), message, "{}", source);
+        }
     }
 }
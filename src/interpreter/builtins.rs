@@ -2,248 +2,1742 @@ use super::value::*;
 use super::DrawCmd;
 use rand::Rng;
 
-pub fn get_builtins() -> Vec<(Vec<&'static str>, Value)> {
+pub fn get_builtins() -> Vec<(Vec<&'static str>, LogoFn)> {
     let mut builtins = vec![];
 
-    fn forward_fn(inter: &mut Interpreter, args: Vec<Value>) -> Value {
+    fn forward_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
         assert_eq!(args.len(), 1);
-        match args[0] {
-            Value::Number(n) => {
-                inter.drawing.push(DrawCmd::Forward(n));
+        match args[0].as_number() {
+            Some(n) => {
+                inter.draw(DrawCmd::Forward(n));
             }
-            _ => panic!("forward error"),
+            _ => return Err(RuntimeError::type_mismatch("forward", &args[0]).into()),
         }
-        Value::Nothing
+        Ok(Value::Nothing)
     }
     builtins.push((
         vec!["forward", "fd"],
         LogoFn::LangFn {
             arity: 1,
+            inputs: 1..=1,
             function: forward_fn,
         },
     ));
 
-    fn back_fn(inter: &mut Interpreter, args: Vec<Value>) -> Value {
+    fn back_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
         assert_eq!(args.len(), 1);
-        match args[0] {
-            Value::Number(n) => {
-                inter.drawing.push(DrawCmd::Back(n));
+        match args[0].as_number() {
+            Some(n) => {
+                inter.draw(DrawCmd::Back(n));
             }
-            _ => panic!("back error"),
+            _ => return Err(RuntimeError::type_mismatch("back", &args[0]).into()),
         }
-        Value::Nothing
+        Ok(Value::Nothing)
     }
     builtins.push((
         vec!["back", "bk"],
         LogoFn::LangFn {
             arity: 1,
+            inputs: 1..=1,
             function: back_fn,
         },
     ));
 
-    fn leftturn_fn(inter: &mut Interpreter, args: Vec<Value>) -> Value {
+    fn leftturn_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
         assert_eq!(args.len(), 1);
-        match args[0] {
-            Value::Number(n) => {
-                inter.drawing.push(DrawCmd::LeftTurn(n));
+        match args[0].as_number() {
+            Some(n) => {
+                inter.draw(DrawCmd::LeftTurn(n));
             }
-            _ => panic!("leftturn error"),
+            _ => return Err(RuntimeError::type_mismatch("left", &args[0]).into()),
         }
-        Value::Nothing
+        Ok(Value::Nothing)
     }
     builtins.push((
         vec!["left", "lt"],
         LogoFn::LangFn {
             arity: 1,
+            inputs: 1..=1,
             function: leftturn_fn,
         },
     ));
 
-    fn rightturn_fn(inter: &mut Interpreter, args: Vec<Value>) -> Value {
+    fn rightturn_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
         assert_eq!(args.len(), 1);
-        match args[0] {
-            Value::Number(n) => {
-                inter.drawing.push(DrawCmd::RightTurn(n));
+        match args[0].as_number() {
+            Some(n) => {
+                inter.draw(DrawCmd::RightTurn(n));
             }
-            _ => panic!("rightturn error"),
+            _ => return Err(RuntimeError::type_mismatch("right", &args[0]).into()),
         }
-        Value::Nothing
+        Ok(Value::Nothing)
     }
     builtins.push((
         vec!["right", "rt"],
         LogoFn::LangFn {
             arity: 1,
+            inputs: 1..=1,
             function: rightturn_fn,
         },
     ));
 
-    fn setcolor_fn(inter: &mut Interpreter, args: Vec<Value>) -> Value {
+    fn setcolor_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
         assert_eq!(args.len(), 1);
         match &args[0] {
             Value::String(s) => {
-                inter.drawing.push(DrawCmd::SetColor(s.clone()));
+                inter.draw(DrawCmd::SetColor(s.clone()));
             }
-            _ => panic!("setcolor error"),
+            _ => return Err(RuntimeError::type_mismatch("setcolor", &args[0]).into()),
         }
-        Value::Nothing
+        Ok(Value::Nothing)
     }
     builtins.push((
         vec!["setcolor"],
         LogoFn::LangFn {
             arity: 1,
+            inputs: 1..=1,
             function: setcolor_fn,
         },
     ));
 
-    fn clearscreen_fn(inter: &mut Interpreter, args: Vec<Value>) -> Value {
+    fn clearscreen_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
         assert_eq!(args.len(), 0);
-        inter.drawing.push(DrawCmd::ClearScreen);
-        Value::Nothing
+        inter.draw(DrawCmd::ClearScreen);
+        Ok(Value::Nothing)
     }
     builtins.push((
         vec!["clearscreen", "cs"],
         LogoFn::LangFn {
             arity: 0,
+            inputs: 0..=0,
             function: clearscreen_fn,
         },
     ));
 
-    fn pick_fn(inter: &mut Interpreter, args: Vec<Value>) -> Value {
+    fn pick_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
         assert_eq!(args.len(), 1);
         match &args[0] {
             Value::List(list) => {
-                if list.len() == 0 {
-                    panic!("pick of empty list");
+                if list.is_empty() {
+                    Err(RuntimeError::new(ErrorKind::EmptyList, "pick", vec![]).into())
                 } else {
                     let mut rng = rand::thread_rng();
                     let index = rng.gen_range(0..list.len());
-                    let v = (&list[index]).clone();
-                    v
+                    Ok(list[index].clone())
                 }
             }
-            _ => panic!("pick error"),
+            _ => Err(RuntimeError::type_mismatch("pick", &args[0]).into()),
         }
     }
     builtins.push((
         vec!["pick"],
         LogoFn::LangFn {
             arity: 1,
+            inputs: 1..=1,
             function: pick_fn,
         },
     ));
 
-    fn random_fn(inter: &mut Interpreter, args: Vec<Value>) -> Value {
+    fn random_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
         assert_eq!(args.len(), 1);
-        match args[0] {
-            Value::Number(n) => {
+        match args[0].as_number() {
+            Some(n) if n >= 1.0 => {
                 let mut rng = rand::thread_rng();
-                Value::Number(rng.gen_range(0..(n as i32)) as f64)
+                Ok(Value::Number(rng.gen_range(0..(n as i32)) as f64))
             }
-            _ => panic!("random error"),
+            _ => Err(RuntimeError::type_mismatch("random", &args[0]).into()),
         }
     }
     builtins.push((
         vec!["random"],
         LogoFn::LangFn {
             arity: 1,
+            inputs: 1..=1,
             function: random_fn,
         },
     ));
 
-    fn stop_fn(inter: &mut Interpreter, args: Vec<Value>) -> Value {
+    fn quotient_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 2);
+        let (a, b) = divisible("quotient", &args)?;
+        Ok(Value::Number((a / b).trunc()))
+    }
+    builtins.push((
+        vec!["quotient"],
+        LogoFn::LangFn {
+            arity: 2,
+            inputs: 2..=2,
+            function: quotient_fn,
+        },
+    ));
+
+    fn remainder_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 2);
+        let (a, b) = divisible("remainder", &args)?;
+        Ok(Value::Number(a % b))
+    }
+    builtins.push((
+        vec!["remainder"],
+        LogoFn::LangFn {
+            arity: 2,
+            inputs: 2..=2,
+            function: remainder_fn,
+        },
+    ));
+
+    /// Like `remainder`, but the result has the sign of the divisor.
+    fn modulo_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 2);
+        let (a, b) = divisible("modulo", &args)?;
+        Ok(Value::Number((a % b + b) % b))
+    }
+    builtins.push((
+        vec!["modulo"],
+        LogoFn::LangFn {
+            arity: 2,
+            inputs: 2..=2,
+            function: modulo_fn,
+        },
+    ));
+
+    fn power_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 2);
+        let (a, b) = numbers("power", &args)?;
+        Ok(Value::Number(a.powf(b)))
+    }
+    builtins.push((
+        vec!["power"],
+        LogoFn::LangFn {
+            arity: 2,
+            inputs: 2..=2,
+            function: power_fn,
+        },
+    ));
+
+    /// Trigonometric functions take and output angles in degrees.
+    fn sin_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 1);
+        let x = number("sin", &args[0])?;
+        Ok(Value::Number(x.to_radians().sin()))
+    }
+    builtins.push((
+        vec!["sin"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 1..=1,
+            function: sin_fn,
+        },
+    ));
+
+    fn cos_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 1);
+        let x = number("cos", &args[0])?;
+        Ok(Value::Number(x.to_radians().cos()))
+    }
+    builtins.push((
+        vec!["cos"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 1..=1,
+            function: cos_fn,
+        },
+    ));
+
+    fn tan_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 1);
+        let x = number("tan", &args[0])?;
+        Ok(Value::Number(x.to_radians().tan()))
+    }
+    builtins.push((
+        vec!["tan"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 1..=1,
+            function: tan_fn,
+        },
+    ));
+
+    /// `(arctan x y)` outputs the angle of the point (x, y).
+    fn arctan_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert!((1..=2).contains(&args.len()));
+        let angle = match &args[..] {
+            [x] => number("arctan", x)?.atan(),
+            _ => {
+                let (x, y) = numbers("arctan", &args)?;
+                y.atan2(x)
+            }
+        };
+        Ok(Value::Number(angle.to_degrees()))
+    }
+    builtins.push((
+        vec!["arctan"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 1..=2,
+            function: arctan_fn,
+        },
+    ));
+
+    fn radsin_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 1);
+        let x = number("radsin", &args[0])?;
+        Ok(Value::Number(x.sin()))
+    }
+    builtins.push((
+        vec!["radsin"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 1..=1,
+            function: radsin_fn,
+        },
+    ));
+
+    fn radcos_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 1);
+        let x = number("radcos", &args[0])?;
+        Ok(Value::Number(x.cos()))
+    }
+    builtins.push((
+        vec!["radcos"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 1..=1,
+            function: radcos_fn,
+        },
+    ));
+
+    fn radtan_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 1);
+        let x = number("radtan", &args[0])?;
+        Ok(Value::Number(x.tan()))
+    }
+    builtins.push((
+        vec!["radtan"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 1..=1,
+            function: radtan_fn,
+        },
+    ));
+
+    fn radarctan_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert!((1..=2).contains(&args.len()));
+        match &args[..] {
+            [x] => Ok(Value::Number(number("radarctan", x)?.atan())),
+            _ => {
+                let (x, y) = numbers("radarctan", &args)?;
+                Ok(Value::Number(y.atan2(x)))
+            }
+        }
+    }
+    builtins.push((
+        vec!["radarctan"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 1..=2,
+            function: radarctan_fn,
+        },
+    ));
+
+    fn sqrt_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 1);
+        let x = number("sqrt", &args[0])?;
+        if x < 0.0 {
+            return Err(RuntimeError::type_mismatch("sqrt", &args[0]).into());
+        }
+        Ok(Value::Number(x.sqrt()))
+    }
+    builtins.push((
+        vec!["sqrt"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 1..=1,
+            function: sqrt_fn,
+        },
+    ));
+
+    fn abs_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 1);
+        let x = number("abs", &args[0])?;
+        Ok(Value::Number(x.abs()))
+    }
+    builtins.push((
+        vec!["abs"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 1..=1,
+            function: abs_fn,
+        },
+    ));
+
+    fn int_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 1);
+        let x = number("int", &args[0])?;
+        Ok(Value::Number(x.trunc()))
+    }
+    builtins.push((
+        vec!["int"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 1..=1,
+            function: int_fn,
+        },
+    ));
+
+    fn round_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 1);
+        let x = number("round", &args[0])?;
+        Ok(Value::Number(x.round()))
+    }
+    builtins.push((
+        vec!["round"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 1..=1,
+            function: round_fn,
+        },
+    ));
+
+    fn exp_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 1);
+        let x = number("exp", &args[0])?;
+        Ok(Value::Number(x.exp()))
+    }
+    builtins.push((
+        vec!["exp"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 1..=1,
+            function: exp_fn,
+        },
+    ));
+
+    fn ln_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 1);
+        let x = number("ln", &args[0])?;
+        if x <= 0.0 {
+            return Err(RuntimeError::type_mismatch("ln", &args[0]).into());
+        }
+        Ok(Value::Number(x.ln()))
+    }
+    builtins.push((
+        vec!["ln"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 1..=1,
+            function: ln_fn,
+        },
+    ));
+
+    fn log10_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 1);
+        let x = number("log10", &args[0])?;
+        if x <= 0.0 {
+            return Err(RuntimeError::type_mismatch("log10", &args[0]).into());
+        }
+        Ok(Value::Number(x.log10()))
+    }
+    builtins.push((
+        vec!["log10"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 1..=1,
+            function: log10_fn,
+        },
+    ));
+
+    fn pi_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 0);
+        Ok(Value::Number(std::f64::consts::PI))
+    }
+    builtins.push((
+        vec!["pi"],
+        LogoFn::LangFn {
+            arity: 0,
+            inputs: 0..=0,
+            function: pi_fn,
+        },
+    ));
+
+    fn min_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert!(!args.is_empty());
+        let mut result = number("min", &args[0])?;
+        for arg in &args[1..] {
+            result = result.min(number("min", arg)?);
+        }
+        Ok(Value::Number(result))
+    }
+    builtins.push((
+        vec!["min"],
+        LogoFn::LangFn {
+            arity: 2,
+            inputs: 1..=usize::MAX,
+            function: min_fn,
+        },
+    ));
+
+    fn max_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert!(!args.is_empty());
+        let mut result = number("max", &args[0])?;
+        for arg in &args[1..] {
+            result = result.max(number("max", arg)?);
+        }
+        Ok(Value::Number(result))
+    }
+    builtins.push((
+        vec!["max"],
+        LogoFn::LangFn {
+            arity: 2,
+            inputs: 1..=usize::MAX,
+            function: max_fn,
+        },
+    ));
+
+    fn first_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 1);
+        match elements("first", &args[0])?.into_iter().next() {
+            Some(v) => Ok(v),
+            None => Err(RuntimeError::new(ErrorKind::EmptyList, "first", vec![]).into()),
+        }
+    }
+    builtins.push((
+        vec!["first"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 1..=1,
+            function: first_fn,
+        },
+    ));
+
+    fn butfirst_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 1);
+        let mut elems = elements("butfirst", &args[0])?;
+        if elems.is_empty() {
+            return Err(RuntimeError::new(ErrorKind::EmptyList, "butfirst", vec![]).into());
+        }
+        elems.remove(0);
+        Ok(same_kind(&args[0], elems))
+    }
+    builtins.push((
+        vec!["butfirst", "bf"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 1..=1,
+            function: butfirst_fn,
+        },
+    ));
+
+    fn last_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 1);
+        match elements("last", &args[0])?.pop() {
+            Some(v) => Ok(v),
+            None => Err(RuntimeError::new(ErrorKind::EmptyList, "last", vec![]).into()),
+        }
+    }
+    builtins.push((
+        vec!["last"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 1..=1,
+            function: last_fn,
+        },
+    ));
+
+    fn butlast_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 1);
+        let mut elems = elements("butlast", &args[0])?;
+        if elems.pop().is_none() {
+            return Err(RuntimeError::new(ErrorKind::EmptyList, "butlast", vec![]).into());
+        }
+        Ok(same_kind(&args[0], elems))
+    }
+    builtins.push((
+        vec!["butlast", "bl"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 1..=1,
+            function: butlast_fn,
+        },
+    ));
+
+    /// Outputs the element of a list, or character of a word, at a 1-based index.
+    fn item_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 2);
+        let elems = elements("item", &args[1])?;
+        match args[0].as_number() {
+            Some(n) if n >= 1.0 && n as usize <= elems.len() => Ok(elems[n as usize - 1].clone()),
+            _ => Err(RuntimeError::type_mismatch("item", &args[0]).into()),
+        }
+    }
+    builtins.push((
+        vec!["item"],
+        LogoFn::LangFn {
+            arity: 2,
+            inputs: 2..=2,
+            function: item_fn,
+        },
+    ));
+
+    fn fput_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 2);
+        match &args[1] {
+            Value::List(list) => {
+                let mut list = list.clone();
+                list.insert(0, args[0].clone());
+                Ok(Value::List(list))
+            }
+            word => match (args[0].as_word(), word.as_word()) {
+                (Some(w1), Some(w2)) => Ok(Value::String(w1 + &w2)),
+                _ => Err(RuntimeError::type_mismatch("fput", word).into()),
+            },
+        }
+    }
+    builtins.push((
+        vec!["fput"],
+        LogoFn::LangFn {
+            arity: 2,
+            inputs: 2..=2,
+            function: fput_fn,
+        },
+    ));
+
+    fn lput_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 2);
+        match &args[1] {
+            Value::List(list) => {
+                let mut list = list.clone();
+                list.push(args[0].clone());
+                Ok(Value::List(list))
+            }
+            word => match (word.as_word(), args[0].as_word()) {
+                (Some(w1), Some(w2)) => Ok(Value::String(w1 + &w2)),
+                _ => Err(RuntimeError::type_mismatch("lput", word).into()),
+            },
+        }
+    }
+    builtins.push((
+        vec!["lput"],
+        LogoFn::LangFn {
+            arity: 2,
+            inputs: 2..=2,
+            function: lput_fn,
+        },
+    ));
+
+    fn list_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        Ok(Value::List(args))
+    }
+    builtins.push((
+        vec!["list"],
+        LogoFn::LangFn {
+            arity: 2,
+            inputs: 0..=usize::MAX,
+            function: list_fn,
+        },
+    ));
+
+    /// Combines its inputs into a list, taking the elements of those that are lists.
+    fn sentence_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        let mut sentence = vec![];
+        for arg in args {
+            match arg {
+                Value::List(list) => sentence.extend(list),
+                word => sentence.push(word),
+            }
+        }
+        Ok(Value::List(sentence))
+    }
+    builtins.push((
+        vec!["sentence", "se"],
+        LogoFn::LangFn {
+            arity: 2,
+            inputs: 0..=usize::MAX,
+            function: sentence_fn,
+        },
+    ));
+
+    fn count_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 1);
+        Ok(Value::Number(elements("count", &args[0])?.len() as f64))
+    }
+    builtins.push((
+        vec!["count"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 1..=1,
+            function: count_fn,
+        },
+    ));
+
+    fn emptyp_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 1);
+        Ok(Value::Bool(elements("emptyp", &args[0])?.is_empty()))
+    }
+    builtins.push((
+        vec!["emptyp", "empty?"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 1..=1,
+            function: emptyp_fn,
+        },
+    ));
+
+    fn memberp_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 2);
+        let elems = elements("memberp", &args[1])?;
+        Ok(Value::Bool(elems.iter().any(|e| e.logo_eq(&args[0]))))
+    }
+    builtins.push((
+        vec!["memberp", "member?"],
+        LogoFn::LangFn {
+            arity: 2,
+            inputs: 2..=2,
+            function: memberp_fn,
+        },
+    ));
+
+    fn reverse_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 1);
+        let mut elems = elements("reverse", &args[0])?;
+        elems.reverse();
+        Ok(same_kind(&args[0], elems))
+    }
+    builtins.push((
+        vec!["reverse"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 1..=1,
+            function: reverse_fn,
+        },
+    ));
+
+    fn word_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        let mut word = String::new();
+        for arg in &args {
+            match arg.as_word() {
+                Some(w) => word.push_str(&w),
+                None => return Err(RuntimeError::type_mismatch("word", arg).into()),
+            }
+        }
+        Ok(Value::String(word))
+    }
+    builtins.push((
+        vec!["word"],
+        LogoFn::LangFn {
+            arity: 2,
+            inputs: 0..=usize::MAX,
+            function: word_fn,
+        },
+    ));
+
+    fn char_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 1);
+        let code = number("char", &args[0])?;
+        if code < 0.0 || code.fract() != 0.0 {
+            return Err(RuntimeError::type_mismatch("char", &args[0]).into());
+        }
+        match char::from_u32(code as u32) {
+            Some(c) => Ok(Value::String(c.to_string())),
+            None => Err(RuntimeError::type_mismatch("char", &args[0]).into()),
+        }
+    }
+    builtins.push((
+        vec!["char"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 1..=1,
+            function: char_fn,
+        },
+    ));
+
+    fn ascii_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 1);
+        let word = args[0].as_word().unwrap_or_default();
+        let mut chars = word.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Ok(Value::Number(c as u32 as f64)),
+            _ => Err(RuntimeError::type_mismatch("ascii", &args[0]).into()),
+        }
+    }
+    builtins.push((
+        vec!["ascii"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 1..=1,
+            function: ascii_fn,
+        },
+    ));
+
+    fn uppercase_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 1);
+        match args[0].as_word() {
+            Some(word) => Ok(Value::String(word.to_uppercase())),
+            None => Err(RuntimeError::type_mismatch("uppercase", &args[0]).into()),
+        }
+    }
+    builtins.push((
+        vec!["uppercase"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 1..=1,
+            function: uppercase_fn,
+        },
+    ));
+
+    fn lowercase_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 1);
+        match args[0].as_word() {
+            Some(word) => Ok(Value::String(word.to_lowercase())),
+            None => Err(RuntimeError::type_mismatch("lowercase", &args[0]).into()),
+        }
+    }
+    builtins.push((
+        vec!["lowercase"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 1..=1,
+            function: lowercase_fn,
+        },
+    ));
+
+    /// `substring word start count` outputs `count` characters of `word`, from
+    /// the one at position `start`, counting from 1.
+    fn substring_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 3);
+        let Some(word) = args[0].as_word() else {
+            return Err(RuntimeError::type_mismatch("substring", &args[0]).into());
+        };
+        let chars: Vec<char> = word.chars().collect();
+        let start = number("substring", &args[1])?;
+        if start < 1.0 || start as usize > chars.len() + 1 {
+            return Err(RuntimeError::type_mismatch("substring", &args[1]).into());
+        }
+        let count = number("substring", &args[2])?;
+        let start = start as usize - 1;
+        if count < 0.0 || start + count as usize > chars.len() {
+            return Err(RuntimeError::type_mismatch("substring", &args[2]).into());
+        }
+        Ok(Value::String(
+            chars[start..start + count as usize].iter().collect(),
+        ))
+    }
+    builtins.push((
+        vec!["substring"],
+        LogoFn::LangFn {
+            arity: 3,
+            inputs: 3..=3,
+            function: substring_fn,
+        },
+    ));
+
+    fn wordp_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 1);
+        Ok(Value::Bool(args[0].as_word().is_some()))
+    }
+    builtins.push((
+        vec!["wordp", "word?"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 1..=1,
+            function: wordp_fn,
+        },
+    ));
+
+    fn print_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        let text: Vec<String> = args.iter().map(printed).collect();
+        inter.write("print", &format!("{}\n", text.join(" ")))?;
+        Ok(Value::Nothing)
+    }
+    builtins.push((
+        vec!["print", "pr"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 0..=usize::MAX,
+            function: print_fn,
+        },
+    ));
+
+    fn show_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        let text: Vec<String> = args.iter().map(|v| v.to_string()).collect();
+        inter.write("show", &format!("{}\n", text.join(" ")))?;
+        Ok(Value::Nothing)
+    }
+    builtins.push((
+        vec!["show"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 0..=usize::MAX,
+            function: show_fn,
+        },
+    ));
+
+    /// Prints like `print`, without a newline or spaces between the inputs.
+    fn type_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        let text: String = args.iter().map(printed).collect();
+        inter.write("type", &text)?;
+        Ok(Value::Nothing)
+    }
+    builtins.push((
+        vec!["type"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 0..=usize::MAX,
+            function: type_fn,
+        },
+    ));
+
+    /// `form number width precision` outputs `number` with `precision` digits
+    /// after the decimal point, padded with spaces to `width` characters.
+    fn form_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 3);
+        let n = number("form", &args[0])?;
+        let (width, precision) = numbers("form", &args[1..])?;
+        // Formatting panics beyond `u16::MAX`, and long before that the text
+        // is of no use.
+        if !(0.0..=1000.0).contains(&width) {
+            return Err(RuntimeError::type_mismatch("form", &args[1]).into());
+        }
+        if !(0.0..=1000.0).contains(&precision) {
+            return Err(RuntimeError::type_mismatch("form", &args[2]).into());
+        }
+        let (width, precision) = (width as usize, precision as usize);
+        Ok(Value::String(format!("{:>width$.precision$}", n)))
+    }
+    builtins.push((
+        vec!["form"],
+        LogoFn::LangFn {
+            arity: 3,
+            inputs: 3..=3,
+            function: form_fn,
+        },
+    ));
+
+    fn test_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 1);
+        match &args[0] {
+            Value::Bool(b) => inter.frames.last_mut().unwrap().test = Some(*b),
+            _ => return Err(RuntimeError::type_mismatch("test", &args[0]).into()),
+        }
+        Ok(Value::Nothing)
+    }
+    builtins.push((
+        vec!["test"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 1..=1,
+            function: test_fn,
+        },
+    ));
+
+    fn stop_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 0);
+        if inter.call_stack.is_empty() {
+            return Err(RuntimeError::new(ErrorKind::NotInProcedure, "stop", vec![]).into());
+        }
+        Err(ControlFlow::Stop)
+    }
+    builtins.push((
+        vec!["stop"],
+        LogoFn::LangFn {
+            arity: 0,
+            inputs: 0..=0,
+            function: stop_fn,
+        },
+    ));
+
+    fn output_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 1);
+        if inter.call_stack.is_empty() {
+            return Err(RuntimeError::new(ErrorKind::NotInProcedure, "output", vec![]).into());
+        }
+        Err(ControlFlow::Output(args[0].clone()))
+    }
+    builtins.push((
+        vec!["output", "op"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 1..=1,
+            function: output_fn,
+        },
+    ));
+
+    fn run_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 1);
+        let block = inter.instructions("run", &args[0])?;
+        inter.eval(&block)
+    }
+    builtins.push((
+        vec!["run"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 1..=1,
+            function: run_fn,
+        },
+    ));
+
+    fn runresult_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 1);
+        let block = inter.instructions("runresult", &args[0])?;
+        match inter.eval(&block)? {
+            Value::Nothing => Ok(Value::List(vec![])),
+            v => Ok(Value::List(vec![v])),
+        }
+    }
+    builtins.push((
+        vec!["runresult"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 1..=1,
+            function: runresult_fn,
+        },
+    ));
+
+    fn apply_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 2);
+        let template = inter.template("apply", &args[0])?;
+        match &args[1] {
+            Value::List(inputs) => inter.invoke(&template, inputs.clone()),
+            v => Err(RuntimeError::type_mismatch("apply", v).into()),
+        }
+    }
+    builtins.push((
+        vec!["apply"],
+        LogoFn::LangFn {
+            arity: 2,
+            inputs: 2..=2,
+            function: apply_fn,
+        },
+    ));
+
+    fn invoke_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert!(!args.is_empty());
+        let template = inter.template("invoke", &args[0])?;
+        inter.invoke(&template, args[1..].to_vec())
+    }
+    builtins.push((
+        vec!["invoke"],
+        LogoFn::LangFn {
+            arity: 2,
+            inputs: 1..=usize::MAX,
+            function: invoke_fn,
+        },
+    ));
+
+    fn foreach_fn(inter: &mut Interpreter, mut args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert!(args.len() >= 2);
+        let template = inter.template("foreach", &args.pop().unwrap())?;
+        for inputs in zip_elements("foreach", &args)? {
+            inter.invoke(&template, inputs)?;
+        }
+        Ok(Value::Nothing)
+    }
+    builtins.push((
+        vec!["foreach"],
+        LogoFn::LangFn {
+            arity: 2,
+            inputs: 2..=usize::MAX,
+            function: foreach_fn,
+        },
+    ));
+
+    fn map_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert!(args.len() >= 2);
+        let template = inter.template("map", &args[0])?;
+        let mut results = vec![];
+        for inputs in zip_elements("map", &args[1..])? {
+            let result = inter.invoke(&template, inputs)?;
+            results.push(output_of("map", &template, result)?);
+        }
+        Ok(same_kind(&args[1], results))
+    }
+    builtins.push((
+        vec!["map"],
+        LogoFn::LangFn {
+            arity: 2,
+            inputs: 2..=usize::MAX,
+            function: map_fn,
+        },
+    ));
+
+    fn filter_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 2);
+        let template = inter.template("filter", &args[0])?;
+        let mut kept = vec![];
+        for e in elements("filter", &args[1])? {
+            let result = inter.invoke(&template, vec![e.clone()])?;
+            match output_of("filter", &template, result)? {
+                Value::Bool(true) => kept.push(e),
+                Value::Bool(false) => {}
+                v => return Err(RuntimeError::type_mismatch("filter", &v).into()),
+            }
+        }
+        Ok(same_kind(&args[1], kept))
+    }
+    builtins.push((
+        vec!["filter"],
+        LogoFn::LangFn {
+            arity: 2,
+            inputs: 2..=2,
+            function: filter_fn,
+        },
+    ));
+
+    /// Combines the elements from the right, so that `reduce "sum [1 2 3]` is
+    /// `sum 1 sum 2 3`.
+    fn reduce_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 2);
+        let template = inter.template("reduce", &args[0])?;
+        let mut elements = elements("reduce", &args[1])?;
+        let Some(mut acc) = elements.pop() else {
+            return Err(RuntimeError::new(ErrorKind::EmptyList, "reduce", vec![]).into());
+        };
+        while let Some(e) = elements.pop() {
+            let result = inter.invoke(&template, vec![e, acc])?;
+            acc = output_of("reduce", &template, result)?;
+        }
+        Ok(acc)
+    }
+    builtins.push((
+        vec!["reduce"],
+        LogoFn::LangFn {
+            arity: 2,
+            inputs: 2..=2,
+            function: reduce_fn,
+        },
+    ));
+
+    fn break_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 0);
+        if inter.frames.last().unwrap().loops == 0 {
+            return Err(RuntimeError::new(ErrorKind::NotInLoop, "break", vec![]).into());
+        }
+        Err(ControlFlow::Break)
+    }
+    builtins.push((
+        vec!["break"],
+        LogoFn::LangFn {
+            arity: 0,
+            inputs: 0..=0,
+            function: break_fn,
+        },
+    ));
+
+    fn continue_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
         assert_eq!(args.len(), 0);
-        Value::Return
+        if inter.frames.last().unwrap().loops == 0 {
+            return Err(RuntimeError::new(ErrorKind::NotInLoop, "continue", vec![]).into());
+        }
+        Err(ControlFlow::Continue)
     }
     builtins.push((
-        vec!["stop"],
+        vec!["continue"],
         LogoFn::LangFn {
             arity: 0,
-            function: stop_fn,
+            inputs: 0..=0,
+            function: continue_fn,
+        },
+    ));
+
+    fn make_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 2);
+        match &args[0] {
+            Value::String(s) => inter.set_variable(s.clone(), args[1].clone()),
+            _ => return Err(RuntimeError::type_mismatch("make", &args[0]).into()),
+        }
+        Ok(Value::Nothing)
+    }
+    builtins.push((
+        vec!["make"],
+        LogoFn::LangFn {
+            arity: 2,
+            inputs: 2..=2,
+            function: make_fn,
+        },
+    ));
+
+    fn name_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 2);
+        match &args[1] {
+            Value::String(s) => inter.set_variable(s.clone(), args[0].clone()),
+            _ => return Err(RuntimeError::type_mismatch("name", &args[1]).into()),
+        }
+        Ok(Value::Nothing)
+    }
+    builtins.push((
+        vec!["name"],
+        LogoFn::LangFn {
+            arity: 2,
+            inputs: 2..=2,
+            function: name_fn,
+        },
+    ));
+
+    fn local_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 1);
+        if inter.frames.len() == 1 {
+            return Err(RuntimeError::new(ErrorKind::NotInProcedure, "local", vec![]).into());
+        }
+        match &args[0] {
+            Value::String(s) => inter.bind_local(s.clone(), Value::Nothing),
+            Value::List(names) => {
+                for n in names {
+                    match n {
+                        Value::String(s) => inter.bind_local(s.clone(), Value::Nothing),
+                        _ => return Err(RuntimeError::type_mismatch("local", n).into()),
+                    }
+                }
+            }
+            _ => return Err(RuntimeError::type_mismatch("local", &args[0]).into()),
+        }
+        Ok(Value::Nothing)
+    }
+    builtins.push((
+        vec!["local"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 1..=1,
+            function: local_fn,
+        },
+    ));
+
+    fn localmake_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 2);
+        if inter.frames.len() == 1 {
+            return Err(RuntimeError::new(ErrorKind::NotInProcedure, "localmake", vec![]).into());
+        }
+        match &args[0] {
+            Value::String(s) => inter.bind_local(s.clone(), args[1].clone()),
+            _ => return Err(RuntimeError::type_mismatch("localmake", &args[0]).into()),
+        }
+        Ok(Value::Nothing)
+    }
+    builtins.push((
+        vec!["localmake"],
+        LogoFn::LangFn {
+            arity: 2,
+            inputs: 2..=2,
+            function: localmake_fn,
+        },
+    ));
+
+    fn thing_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 1);
+        match &args[0] {
+            Value::String(s) => Ok(inter.lookup(s)?),
+            _ => Err(RuntimeError::type_mismatch("thing", &args[0]).into()),
+        }
+    }
+    builtins.push((
+        vec!["thing"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 1..=1,
+            function: thing_fn,
         },
     ));
 
-    fn penup_fn(inter: &mut Interpreter, args: Vec<Value>) -> Value {
+    fn penup_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
         assert_eq!(args.len(), 0);
-        inter.drawing.push(DrawCmd::PenUp);
-        Value::Nothing
+        inter.draw(DrawCmd::PenUp);
+        Ok(Value::Nothing)
     }
     builtins.push((
         vec!["penup", "pu"],
         LogoFn::LangFn {
             arity: 0,
+            inputs: 0..=0,
             function: penup_fn,
         },
     ));
 
-    fn pendown_fn(inter: &mut Interpreter, args: Vec<Value>) -> Value {
+    fn pendown_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
         assert_eq!(args.len(), 0);
-        inter.drawing.push(DrawCmd::PenDown);
-        Value::Nothing
+        inter.draw(DrawCmd::PenDown);
+        Ok(Value::Nothing)
     }
     builtins.push((
         vec!["pendown", "pd"],
         LogoFn::LangFn {
             arity: 0,
+            inputs: 0..=0,
             function: pendown_fn,
         },
     ));
 
-    fn label_fn(inter: &mut Interpreter, args: Vec<Value>) -> Value {
+    fn label_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
         assert_eq!(args.len(), 1);
-        match &args[0] {
-            Value::String(s) => {
-                inter.drawing.push(DrawCmd::Label(s.clone()));
-            }
-            _ => panic!("label error"),
+        match args[0].as_word() {
+            Some(s) => inter.draw(DrawCmd::Label(s)),
+            None => return Err(RuntimeError::type_mismatch("label", &args[0]).into()),
         }
-        Value::Nothing
+        Ok(Value::Nothing)
     }
     builtins.push((
         vec!["label"],
         LogoFn::LangFn {
             arity: 1,
+            inputs: 1..=1,
             function: label_fn,
         },
     ));
 
-    fn setfontsize_fn(inter: &mut Interpreter, args: Vec<Value>) -> Value {
+    fn setfontsize_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
         assert_eq!(args.len(), 1);
-        match args[0] {
-            Value::Number(n) => {
-                inter.drawing.push(DrawCmd::SetFontSize(n));
+        match args[0].as_number() {
+            Some(n) => {
+                inter.draw(DrawCmd::SetFontSize(n));
             }
-            _ => panic!("setfontsize error"),
+            _ => return Err(RuntimeError::type_mismatch("setfontsize", &args[0]).into()),
         }
-        Value::Nothing
+        Ok(Value::Nothing)
     }
     builtins.push((
         vec!["setfontsize"],
         LogoFn::LangFn {
             arity: 1,
+            inputs: 1..=1,
             function: setfontsize_fn,
         },
     ));
 
-    fn setturtle_fn(inter: &mut Interpreter, args: Vec<Value>) -> Value {
+    fn setturtle_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
         assert_eq!(args.len(), 1);
-        match args[0] {
-            Value::Number(n) => {
-                inter.drawing.push(DrawCmd::SetTurtle(n as i32));
+        match args[0].as_number() {
+            Some(n) => {
+                inter.draw(DrawCmd::SetTurtle(n as i32));
             }
-            _ => panic!("setturtle error"),
+            _ => return Err(RuntimeError::type_mismatch("setturtle", &args[0]).into()),
         }
-        Value::Nothing
+        Ok(Value::Nothing)
     }
     builtins.push((
         vec!["setturtle"],
         LogoFn::LangFn {
             arity: 1,
+            inputs: 1..=1,
             function: setturtle_fn,
         },
     ));
 
+    fn setxy_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 2);
+        let (x, y) = numbers("setxy", &args)?;
+        inter.draw(DrawCmd::SetXY(x, y));
+        Ok(Value::Nothing)
+    }
+    builtins.push((
+        vec!["setxy"],
+        LogoFn::LangFn {
+            arity: 2,
+            inputs: 2..=2,
+            function: setxy_fn,
+        },
+    ));
+
+    fn setpos_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 1);
+        let (x, y) = point("setpos", &args[0])?;
+        inter.draw(DrawCmd::SetXY(x, y));
+        Ok(Value::Nothing)
+    }
+    builtins.push((
+        vec!["setpos"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 1..=1,
+            function: setpos_fn,
+        },
+    ));
+
+    fn setx_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 1);
+        inter.draw(DrawCmd::SetX(number("setx", &args[0])?));
+        Ok(Value::Nothing)
+    }
+    builtins.push((
+        vec!["setx"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 1..=1,
+            function: setx_fn,
+        },
+    ));
+
+    fn sety_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 1);
+        inter.draw(DrawCmd::SetY(number("sety", &args[0])?));
+        Ok(Value::Nothing)
+    }
+    builtins.push((
+        vec!["sety"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 1..=1,
+            function: sety_fn,
+        },
+    ));
+
+    fn setheading_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 1);
+        inter.draw(DrawCmd::SetHeading(number("setheading", &args[0])?));
+        Ok(Value::Nothing)
+    }
+    builtins.push((
+        vec!["setheading", "seth"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 1..=1,
+            function: setheading_fn,
+        },
+    ));
+
+    fn home_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 0);
+        inter.draw(DrawCmd::Home);
+        Ok(Value::Nothing)
+    }
+    builtins.push((
+        vec!["home"],
+        LogoFn::LangFn {
+            arity: 0,
+            inputs: 0..=0,
+            function: home_fn,
+        },
+    ));
+
+    fn arc_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 2);
+        let (angle, radius) = numbers("arc", &args)?;
+        if !angle.is_finite() {
+            return Err(RuntimeError::type_mismatch("arc", &args[0]).into());
+        }
+        if !radius.is_finite() || radius < 0.0 {
+            return Err(RuntimeError::type_mismatch("arc", &args[1]).into());
+        }
+        inter.draw(DrawCmd::Arc(angle, radius));
+        Ok(Value::Nothing)
+    }
+    builtins.push((
+        vec!["arc"],
+        LogoFn::LangFn {
+            arity: 2,
+            inputs: 2..=2,
+            function: arc_fn,
+        },
+    ));
+
+    fn circle_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 1);
+        let radius = number("circle", &args[0])?;
+        if !radius.is_finite() || radius < 0.0 {
+            return Err(RuntimeError::type_mismatch("circle", &args[0]).into());
+        }
+        inter.draw(DrawCmd::Arc(360.0, radius));
+        Ok(Value::Nothing)
+    }
+    builtins.push((
+        vec!["circle"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 1..=1,
+            function: circle_fn,
+        },
+    ));
+
+    fn arc2_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 2);
+        let (angle, radius) = numbers("arc2", &args)?;
+        if !angle.is_finite() {
+            return Err(RuntimeError::type_mismatch("arc2", &args[0]).into());
+        }
+        if !radius.is_finite() || radius < 0.0 {
+            return Err(RuntimeError::type_mismatch("arc2", &args[1]).into());
+        }
+        inter.draw(DrawCmd::Arc2(angle, radius));
+        Ok(Value::Nothing)
+    }
+    builtins.push((
+        vec!["arc2"],
+        LogoFn::LangFn {
+            arity: 2,
+            inputs: 2..=2,
+            function: arc2_fn,
+        },
+    ));
+
+    fn setfillcolor_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 1);
+        match &args[0] {
+            Value::String(s) => inter.draw(DrawCmd::SetFillColor(s.clone())),
+            _ => return Err(RuntimeError::type_mismatch("setfillcolor", &args[0]).into()),
+        }
+        Ok(Value::Nothing)
+    }
+    builtins.push((
+        vec!["setfillcolor"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 1..=1,
+            function: setfillcolor_fn,
+        },
+    ));
+
+    fn beginfill_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 0);
+        inter.draw(DrawCmd::BeginFill);
+        Ok(Value::Nothing)
+    }
+    builtins.push((
+        vec!["beginfill"],
+        LogoFn::LangFn {
+            arity: 0,
+            inputs: 0..=0,
+            function: beginfill_fn,
+        },
+    ));
+
+    fn endfill_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 0);
+        inter.draw(DrawCmd::EndFill);
+        Ok(Value::Nothing)
+    }
+    builtins.push((
+        vec!["endfill"],
+        LogoFn::LangFn {
+            arity: 0,
+            inputs: 0..=0,
+            function: endfill_fn,
+        },
+    ));
+
+    /// `filled color instructions` runs the instructions as one shape, filled
+    /// with `color` while the fill color stays as it was for later shapes.
+    fn filled_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 2);
+        let Value::String(color) = &args[0] else {
+            return Err(RuntimeError::type_mismatch("filled", &args[0]).into());
+        };
+        let block = inter.instructions("filled", &args[1])?;
+        let previous = inter.turtles.current().fill_color.clone();
+        inter.draw(DrawCmd::SetFillColor(color.clone()));
+        inter.draw(DrawCmd::BeginFill);
+        let ret = inter.eval(&block);
+        inter.draw(DrawCmd::EndFill);
+        inter.draw(DrawCmd::SetFillColor(previous));
+        ret.map(|_| Value::Nothing)
+    }
+    builtins.push((
+        vec!["filled"],
+        LogoFn::LangFn {
+            arity: 2,
+            inputs: 2..=2,
+            function: filled_fn,
+        },
+    ));
+
+    fn pos_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 0);
+        let turtle = inter.turtles.current();
+        Ok(Value::List(vec![
+            Value::Number(turtle.x),
+            Value::Number(turtle.y),
+        ]))
+    }
+    builtins.push((
+        vec!["pos"],
+        LogoFn::LangFn {
+            arity: 0,
+            inputs: 0..=0,
+            function: pos_fn,
+        },
+    ));
+
+    fn xcor_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 0);
+        Ok(Value::Number(inter.turtles.current().x))
+    }
+    builtins.push((
+        vec!["xcor"],
+        LogoFn::LangFn {
+            arity: 0,
+            inputs: 0..=0,
+            function: xcor_fn,
+        },
+    ));
+
+    fn ycor_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 0);
+        Ok(Value::Number(inter.turtles.current().y))
+    }
+    builtins.push((
+        vec!["ycor"],
+        LogoFn::LangFn {
+            arity: 0,
+            inputs: 0..=0,
+            function: ycor_fn,
+        },
+    ));
+
+    fn heading_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 0);
+        Ok(Value::Number(inter.turtles.current().heading))
+    }
+    builtins.push((
+        vec!["heading"],
+        LogoFn::LangFn {
+            arity: 0,
+            inputs: 0..=0,
+            function: heading_fn,
+        },
+    ));
+
+    fn pendownp_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 0);
+        Ok(Value::Bool(inter.turtles.current().pendown))
+    }
+    builtins.push((
+        vec!["pendownp", "pendown?"],
+        LogoFn::LangFn {
+            arity: 0,
+            inputs: 0..=0,
+            function: pendownp_fn,
+        },
+    ));
+
+    fn pencolor_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 0);
+        Ok(Value::String(inter.turtles.current().color.clone()))
+    }
+    builtins.push((
+        vec!["pencolor", "pc"],
+        LogoFn::LangFn {
+            arity: 0,
+            inputs: 0..=0,
+            function: pencolor_fn,
+        },
+    ));
+
+    fn towards_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 1);
+        let (x, y) = point("towards", &args[0])?;
+        Ok(Value::Number(inter.turtles.current().towards(x, y)))
+    }
+    builtins.push((
+        vec!["towards"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 1..=1,
+            function: towards_fn,
+        },
+    ));
+
+    fn distance_fn(inter: &mut Interpreter, args: Vec<Value>) -> Result<Value, ControlFlow> {
+        assert_eq!(args.len(), 1);
+        let (x, y) = point("distance", &args[0])?;
+        Ok(Value::Number(inter.turtles.current().distance(x, y)))
+    }
+    builtins.push((
+        vec!["distance"],
+        LogoFn::LangFn {
+            arity: 1,
+            inputs: 1..=1,
+            function: distance_fn,
+        },
+    ));
+
     builtins
-        .into_iter()
-        .map(|(n, f)| (n, Value::Function(f)))
-        .collect()
+}
+
+/// The numeric value of a number, or of a word that spells one.
+fn number(procedure: &str, arg: &Value) -> Result<f64, Box<RuntimeError>> {
+    match arg.as_number() {
+        Some(n) => Ok(n),
+        None => Err(RuntimeError::type_mismatch(procedure, arg).into()),
+    }
+}
+
+/// The coordinates in a list such as `[10 -20]`.
+fn point(procedure: &str, arg: &Value) -> Result<(f64, f64), Box<RuntimeError>> {
+    match arg {
+        Value::List(list) if list.len() == 2 => match (list[0].as_number(), list[1].as_number()) {
+            (Some(x), Some(y)) => Ok((x, y)),
+            _ => Err(RuntimeError::type_mismatch(procedure, arg).into()),
+        },
+        _ => Err(RuntimeError::type_mismatch(procedure, arg).into()),
+    }
+}
+
+/// A value as `print` writes it: lists without their outer brackets.
+fn printed(value: &Value) -> String {
+    match value {
+        Value::List(list) => {
+            let elements: Vec<String> = list.iter().map(|v| v.to_string()).collect();
+            elements.join(" ")
+        }
+        v => v.to_string(),
+    }
+}
+
+/// The inputs of a procedure that takes two numbers.
+fn numbers(procedure: &str, args: &[Value]) -> Result<(f64, f64), Box<RuntimeError>> {
+    Ok((number(procedure, &args[0])?, number(procedure, &args[1])?))
+}
+
+/// The inputs of a division, which must not be by zero.
+fn divisible(procedure: &str, args: &[Value]) -> Result<(f64, f64), Box<RuntimeError>> {
+    match numbers(procedure, args)? {
+        (_, 0.0) => {
+            Err(RuntimeError::new(ErrorKind::DivisionByZero, procedure, args.to_vec()).into())
+        }
+        (a, b) => Ok((a, b)),
+    }
+}
+
+/// The elements of a list, or the characters of a word.
+fn elements(procedure: &str, thing: &Value) -> Result<Vec<Value>, Box<RuntimeError>> {
+    match (thing, thing.as_word()) {
+        (Value::List(list), _) => Ok(list.clone()),
+        (_, Some(word)) => Ok(word.chars().map(|c| Value::String(c.to_string())).collect()),
+        _ => Err(RuntimeError::type_mismatch(procedure, thing).into()),
+    }
+}
+
+/// A list made of `elements` if `like` is a list, or else a word.
+fn same_kind(like: &Value, elements: Vec<Value>) -> Value {
+    match like {
+        Value::List(_) => Value::List(elements),
+        _ => Value::String(elements.iter().map(|e| e.to_string()).collect()),
+    }
+}
+
+/// The inputs of each call made by `procedure` over several lists or words of
+/// the same length: their first elements, then their second elements...
+fn zip_elements(procedure: &str, data: &[Value]) -> Result<Vec<Vec<Value>>, Box<RuntimeError>> {
+    let lists = data
+        .iter()
+        .map(|d| elements(procedure, d))
+        .collect::<Result<Vec<_>, _>>()?;
+    if let Some(i) = lists.iter().position(|l| l.len() != lists[0].len()) {
+        return Err(RuntimeError::type_mismatch(procedure, &data[i]).into());
+    }
+    Ok((0..lists[0].len())
+        .map(|i| lists.iter().map(|l| l[i].clone()).collect())
+        .collect())
+}
+
+/// The value that `template` output to `procedure`.
+fn output_of(
+    procedure: &str,
+    template: &(String, LogoFn),
+    value: Value,
+) -> Result<Value, Box<RuntimeError>> {
+    match value {
+        Value::Nothing => {
+            Err(
+                RuntimeError::new(ErrorKind::NoOutput(template.0.clone()), procedure, vec![])
+                    .into(),
+            )
+        }
+        v => Ok(v),
+    }
 }
@@ -0,0 +1,224 @@
+use super::super::parser::{Rule, Span};
+use super::value::Value;
+use pest::error::{Error, ErrorVariant};
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ErrorKind {
+    /// An input of the wrong type or outside of the accepted range.
+    TypeMismatch,
+    /// A variable without a value.
+    Unbound(String),
+    /// A call to a procedure that is not defined.
+    UnknownProcedure(String),
+    /// A call with the wrong number of inputs.
+    Arity {
+        expected: usize,
+        got: usize,
+    },
+    EmptyList,
+    DivisionByZero,
+    /// An attempt to redefine a builtin.
+    Primitive(String),
+    /// `iftrue` or `iffalse` before any `test`.
+    NoTest,
+    /// More loop iterations than the interpreter allows.
+    IterationLimit(usize),
+    /// More nested procedure calls than the interpreter allows.
+    CallLimit(usize),
+    /// `stop` or `output` outside of a procedure.
+    NotInProcedure,
+    /// `break` or `continue` outside of a loop.
+    NotInLoop,
+    /// Instructions built at run time that do not parse.
+    Syntax(String),
+    /// A template that did not output a value to the procedure that needs it.
+    NoOutput(String),
+    /// Text that could not be written out.
+    Write(String),
+    /// A value output by instructions that do nothing with it.
+    Unused,
+}
+
+/// An active invocation of a user-defined procedure.
+#[derive(Clone, Debug)]
+pub struct CallInfo {
+    pub proc_name: String,
+    /// Parameters paired with the inputs they were bound to.
+    pub args: Vec<(String, Value)>,
+    pub call_site: Span,
+}
+
+impl fmt::Display for CallInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.proc_name)?;
+        for (param, value) in &self.args {
+            write!(f, " :{}={}", param, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Why evaluation left a block early: either an error, or a primitive that
+/// unwinds to the enclosing procedure call (`stop`, `output`) or loop
+/// (`break`, `continue`). Errors are boxed to keep results small, since they
+/// are passed up through every level of recursion.
+#[derive(Clone, Debug)]
+pub enum ControlFlow {
+    Error(Box<RuntimeError>),
+    Stop,
+    Output(Value),
+    Break,
+    Continue,
+}
+
+impl ControlFlow {
+    /// Sets the location of an error; other transfers are left untouched.
+    pub fn at(self, span: &Span) -> Self {
+        match self {
+            ControlFlow::Error(err) => err.at(span).into(),
+            flow => flow,
+        }
+    }
+}
+
+impl From<RuntimeError> for ControlFlow {
+    fn from(err: RuntimeError) -> Self {
+        ControlFlow::Error(Box::new(err))
+    }
+}
+
+impl From<Box<RuntimeError>> for ControlFlow {
+    fn from(err: Box<RuntimeError>) -> Self {
+        ControlFlow::Error(err)
+    }
+}
+
+/// Calls shown from each end of a traceback too long to show in full.
+const TRACEBACK_ENDS: usize = 5;
+
+#[derive(Clone, Debug)]
+pub struct RuntimeError {
+    pub kind: ErrorKind,
+    /// Name of the procedure or operator that failed.
+    pub procedure: String,
+    /// Values that caused the failure.
+    pub values: Vec<Value>,
+    /// Location of the innermost node whose evaluation failed.
+    pub span: Option<Span>,
+    /// Procedure calls active when the error occurred, innermost first.
+    pub traceback: Vec<CallInfo>,
+}
+
+impl RuntimeError {
+    pub fn new(kind: ErrorKind, procedure: &str, values: Vec<Value>) -> Self {
+        Self {
+            kind,
+            procedure: procedure.to_string(),
+            values,
+            span: None,
+            traceback: vec![],
+        }
+    }
+
+    pub fn type_mismatch(procedure: &str, value: &Value) -> Self {
+        Self::new(ErrorKind::TypeMismatch, procedure, vec![value.clone()])
+    }
+
+    /// Sets the location of the error, unless it is already known.
+    pub fn at(mut self, span: &Span) -> Self {
+        self.span.get_or_insert(*span);
+        self
+    }
+
+    /// Records the call stack, unless an inner call already did.
+    pub fn within(mut self, call_stack: &[CallInfo]) -> Self {
+        if self.traceback.is_empty() {
+            self.traceback = call_stack.iter().rev().cloned().collect();
+        }
+        self
+    }
+
+    /// Formats the error with an excerpt of `source` pointing at its location,
+    /// followed by the procedure calls that led to it.
+    pub fn render(&self, source: &str, path: &str) -> String {
+        let span = self
+            .span
+            .and_then(|span| pest::Span::new(source, span.start, span.end));
+        let mut out = match span {
+            Some(span) => {
+                let message = self.to_string();
+                Error::<Rule>::new_from_span(ErrorVariant::CustomError { message }, span)
+                    .with_path(path)
+                    .to_string()
+            }
+            None => self.to_string(),
+        };
+        if let Some(call) = self.traceback.first() {
+            out.push_str(&format!("\nin {}", call));
+        }
+        // Deep recursion only shows the calls at both ends of the stack.
+        let len = self.traceback.len();
+        let skipped = len.saturating_sub(2 * TRACEBACK_ENDS);
+        for (i, call) in self.traceback.iter().enumerate() {
+            if skipped > 0 && i == TRACEBACK_ENDS {
+                out.push_str(&format!("\n  ... {} more calls", skipped));
+            }
+            if skipped > 0 && (TRACEBACK_ENDS..len - TRACEBACK_ENDS).contains(&i) {
+                continue;
+            }
+            let caller = match self.traceback.get(i + 1) {
+                Some(caller) => caller.to_string(),
+                None => "toplevel".to_string(),
+            };
+            out.push_str(&format!(
+                "\n  called from {} at {}:{}",
+                caller, call.call_site.line, call.call_site.col
+            ));
+        }
+        out
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            ErrorKind::TypeMismatch => {
+                write!(f, "{} doesn't like", self.procedure)?;
+                for v in &self.values {
+                    write!(f, " {}", v)?;
+                }
+                write!(f, " as input")
+            }
+            ErrorKind::Unbound(name) => write!(f, "{} has no value", name),
+            ErrorKind::UnknownProcedure(name) => write!(f, "I don't know how to {}", name),
+            ErrorKind::Arity { expected, got } => write!(
+                f,
+                "{} expects {} inputs, but got {}",
+                self.procedure, expected, got
+            ),
+            ErrorKind::EmptyList => write!(f, "{} of an empty list", self.procedure),
+            ErrorKind::DivisionByZero => write!(f, "{}: division by zero", self.procedure),
+            ErrorKind::Primitive(name) => write!(f, "{} is a primitive", name),
+            ErrorKind::NoTest => write!(f, "{} without test", self.procedure),
+            ErrorKind::NotInProcedure => {
+                write!(f, "{} can only be used inside a procedure", self.procedure)
+            }
+            ErrorKind::NotInLoop => write!(f, "{} can only be used inside a loop", self.procedure),
+            ErrorKind::Syntax(message) => write!(f, "{}: {}", self.procedure, message),
+            ErrorKind::NoOutput(name) => write!(f, "{} didn't output to {}", name, self.procedure),
+            ErrorKind::Write(message) => write!(f, "{}: {}", self.procedure, message),
+            ErrorKind::Unused => write!(f, "You don't say what to do with {}", self.values[0]),
+            ErrorKind::IterationLimit(limit) => write!(
+                f,
+                "{} stopped after reaching the limit of {} iterations",
+                self.procedure, limit
+            ),
+            ErrorKind::CallLimit(limit) => write!(
+                f,
+                "{} stopped after reaching the limit of {} nested calls",
+                self.procedure, limit
+            ),
+        }
+    }
+}
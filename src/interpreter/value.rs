@@ -1,25 +1,160 @@
+use super::super::parser::number_word;
 pub use super::super::parser::AstNode;
+pub use super::error::{ControlFlow, ErrorKind, RuntimeError};
 pub use super::Interpreter;
+use std::cmp::Ordering;
+use std::fmt;
+use std::iter::zip;
+use std::ops::RangeInclusive;
 
 #[derive(Clone, Debug)]
 pub enum LogoFn {
     LangFn {
+        /// Number of inputs taken by a call that is not in parentheses.
         arity: usize,
-        function: fn(&mut Interpreter, Vec<Value>) -> Value,
+        /// Numbers of inputs accepted in parentheses, as in `(arctan 1 2)`.
+        inputs: RangeInclusive<usize>,
+        function: fn(&mut Interpreter, Vec<Value>) -> Result<Value, ControlFlow>,
     },
     UserFn {
         params: Vec<String>,
         body: AstNode,
     },
+    /// Instructions given as input to a procedure such as `map`, whose value is
+    /// that of the last one. Inputs are bound to `params`, or to the slots `?`,
+    /// `?1`, `?2`... when there are none.
+    Template {
+        params: Vec<String>,
+        body: AstNode,
+    },
+}
+
+impl LogoFn {
+    /// Number of inputs taken without parentheses, and the most accepted in them.
+    pub fn arities(&self) -> (usize, usize) {
+        match self {
+            LogoFn::LangFn { arity, inputs, .. } => (*arity, *inputs.end()),
+            LogoFn::UserFn { params, .. } | LogoFn::Template { params, .. } => {
+                (params.len(), params.len())
+            }
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
 pub enum Value {
     Nothing,
-    Return,
     Bool(bool),
     Number(f64),
     String(String),
     List(Vec<Value>),
-    Function(LogoFn),
+}
+
+impl Value {
+    /// The numeric value of numbers and of words that spell one.
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            Value::Number(n) => Some(*n),
+            Value::String(s) => number_word(s),
+            _ => None,
+        }
+    }
+
+    /// The text of words, including numbers and booleans.
+    pub fn as_word(&self) -> Option<String> {
+        match self {
+            Value::String(s) => Some(s.clone()),
+            Value::Number(_) | Value::Bool(_) => Some(self.to_string()),
+            _ => None,
+        }
+    }
+
+    /// The value as it would be written in a list, with a backslash before
+    /// the characters that would otherwise end or split a word, so that
+    /// instruction lists read back as they were.
+    pub fn to_readable(&self) -> String {
+        match self {
+            Value::String(s) => {
+                let mut text = String::new();
+                for c in s.chars() {
+                    if c.is_whitespace() || "[]|\\;".contains(c) {
+                        text.push('\\');
+                    }
+                    text.push(c);
+                }
+                text
+            }
+            Value::List(list) => {
+                let elements: Vec<String> = list.iter().map(|v| v.to_readable()).collect();
+                format!("[{}]", elements.join(" "))
+            }
+            v => v.to_string(),
+        }
+    }
+
+    /// Equality as in Logo: numbers by value, words ignoring case and lists
+    /// element by element.
+    pub fn logo_eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::List(l1), Value::List(l2)) => {
+                l1.len() == l2.len() && zip(l1, l2).all(|(v1, v2)| v1.logo_eq(v2))
+            }
+            (Value::Bool(b1), Value::Bool(b2)) => b1 == b2,
+            _ => match (self.as_number(), other.as_number()) {
+                (Some(n1), Some(n2)) => n1 == n2,
+                _ => match (self, other) {
+                    (Value::String(s1), Value::String(s2)) => {
+                        s1.to_lowercase() == s2.to_lowercase()
+                    }
+                    _ => false,
+                },
+            },
+        }
+    }
+
+    /// Orders numbers by value, words alphabetically ignoring case and lists
+    /// lexicographically; other combinations are not comparable.
+    pub fn logo_cmp(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::List(l1), Value::List(l2)) => {
+                for (v1, v2) in zip(l1, l2) {
+                    match v1.logo_cmp(v2)? {
+                        Ordering::Equal => continue,
+                        ord => return Some(ord),
+                    }
+                }
+                Some(l1.len().cmp(&l2.len()))
+            }
+            _ => match (self.as_number(), other.as_number()) {
+                (Some(n1), Some(n2)) => n1.partial_cmp(&n2),
+                _ => match (self, other) {
+                    (Value::String(s1), Value::String(s2)) => {
+                        Some(s1.to_lowercase().cmp(&s2.to_lowercase()))
+                    }
+                    _ => None,
+                },
+            },
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Nothing => Ok(()),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::String(s) => write!(f, "{}", s),
+            Value::List(list) => {
+                write!(f, "[")?;
+                for (i, v) in list.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{}", v)?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
 }
@@ -4,8 +4,10 @@
 mod drawer;
 mod interpreter;
 mod parser;
+mod turtle;
 
 use std::env;
+use std::fs;
 use std::process;
 
 fn main() {
@@ -23,7 +25,17 @@ fn main() {
     let img_width = args[3].parse::<u32>().unwrap();
     let img_height = args[4].parse::<u32>().unwrap();
 
-    let ast = parser::parse_logo_file(source);
-    let cmds = interpreter::evaluate(&ast);
+    let text = fs::read_to_string(source).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+    let ast = parser::parse_logo_source(&text).unwrap_or_else(|err| {
+        eprintln!("{}", err.with_path(source));
+        process::exit(1);
+    });
+    let cmds = interpreter::evaluate(&ast).unwrap_or_else(|err| {
+        eprintln!("{}", err.render(&text, source));
+        process::exit(1);
+    });
     drawer::draw(destination, cmds, img_width, img_height);
 }
@@ -1,14 +1,55 @@
-use pest::error::Error;
+use super::interpreter::builtin_arities;
+use pest::error::{Error, ErrorVariant, InputLocation};
 use pest::iterators::Pair;
-use pest::Parser;
+use pest::pratt_parser::{Assoc, Op, PrattParser};
+use pest::{Parser, Position};
 use pest_derive::Parser;
-use std::fs;
-use std::process;
+use std::collections::HashMap;
+use std::iter::Peekable;
+use std::sync::OnceLock;
 
 #[derive(Parser)]
 #[grammar = "logo.pest"]
 struct LogoParser;
 
+/// Location of a node in the source: a byte range and the line and column
+/// at which it starts.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub col: usize,
+}
+
+impl Span {
+    /// The span from `start` to `end` in `source`, whose lines begin at the
+    /// offsets in `lines`, so that the source is not scanned again.
+    fn within(source: &str, lines: &[usize], start: usize, end: usize) -> Self {
+        let line = lines.partition_point(|&offset| offset <= start);
+        let col = source[lines[line - 1]..start].chars().count() + 1;
+        Self {
+            start,
+            end,
+            line,
+            col,
+        }
+    }
+
+    #[cfg(test)]
+    fn new(source: &str, start: usize, end: usize) -> Self {
+        Self::within(source, &line_starts(source), start, end)
+    }
+
+    /// Extends the span up to the end of `other`.
+    fn to(self, other: &Span) -> Self {
+        Self {
+            end: other.end,
+            ..self
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Binop {
     And,
@@ -18,130 +59,329 @@ pub enum Binop {
     Greater,
     GreaterEqual,
     EqualEqual,
+    NotEqual,
     Add,
     Sub,
     Mul,
     Div,
+    Rem,
+    Pow,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Unop {
+    Neg,
+    Not,
+}
+
+/// What decides how many times a loop runs its body. Conditions are blocks,
+/// evaluated anew before each iteration, or after it for `do.while` and
+/// `do.until`.
+#[derive(Debug, PartialEq, Clone)]
+pub enum LoopKind {
+    Repeat(Box<AstNode>),
+    While(Box<AstNode>),
+    Until(Box<AstNode>),
+    DoWhile(Box<AstNode>),
+    DoUntil(Box<AstNode>),
+    For {
+        var: String,
+        start: Box<AstNode>,
+        end: Box<AstNode>,
+        step: Option<Box<AstNode>>,
+    },
+    Forever,
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum AstNode {
-    String(String),
-    Number(f64),
-    Variable(String),
-    List(Vec<AstNode>),
+    String(String, Span),
+    Number(f64, Span),
+    Variable(String, Span),
+    List(Vec<AstNode>, Span),
+    Unop {
+        op: Unop,
+        operand: Box<AstNode>,
+        span: Span,
+    },
     Binop {
         lhs: Box<AstNode>,
         op: Binop,
         rhs: Box<AstNode>,
+        span: Span,
     },
     Call {
         f: Box<AstNode>,
         args: Vec<AstNode>,
+        span: Span,
     },
     If {
         condition: Box<AstNode>,
         body: Box<AstNode>,
+        else_body: Option<Box<AstNode>>,
+        span: Span,
+    },
+    /// `iftrue` or `iffalse`, which run `body` depending on the last `test`.
+    IfTest {
+        expected: bool,
+        body: Box<AstNode>,
+        span: Span,
     },
     Loop {
-        repeat: Box<AstNode>,
+        kind: LoopKind,
         body: Box<AstNode>,
+        span: Span,
     },
     ProcDef {
         proc_name: String,
         params: Vec<String>,
         body: Box<AstNode>,
+        span: Span,
     },
-    Block(Vec<AstNode>),
+    Block(Vec<AstNode>, Span),
+    /// The text of a `;` comment, without the semicolon.
+    Comment(String, Span),
+}
+
+impl AstNode {
+    pub fn span(&self) -> &Span {
+        match self {
+            AstNode::String(_, span)
+            | AstNode::Number(_, span)
+            | AstNode::Variable(_, span)
+            | AstNode::List(_, span)
+            | AstNode::Block(_, span)
+            | AstNode::Comment(_, span) => span,
+            AstNode::Unop { span, .. }
+            | AstNode::Binop { span, .. }
+            | AstNode::Call { span, .. }
+            | AstNode::If { span, .. }
+            | AstNode::IfTest { span, .. }
+            | AstNode::Loop { span, .. }
+            | AstNode::ProcDef { span, .. } => span,
+        }
+    }
+}
+
+pub fn parse_logo_source(source: &str) -> Result<AstNode, Box<Error<Rule>>> {
+    let lines = line_starts(source);
+    let mut builder = AstBuilder {
+        source,
+        lines: &lines,
+        offset: 0,
+        arities: builtin_arities(),
+        comments: vec![],
+        location: None,
+    };
+    builder.parse_block(source)
+}
+
+/// Parses instructions built at run time, such as the text of a list given to
+/// `run`. Every node is located at `span`, where the instructions are run.
+pub fn parse_instructions(
+    text: &str,
+    arities: HashMap<String, (usize, usize)>,
+    span: Span,
+) -> Result<AstNode, Box<Error<Rule>>> {
+    let mut builder = AstBuilder {
+        source: text,
+        lines: &[],
+        offset: 0,
+        arities,
+        comments: vec![],
+        location: Some(span),
+    };
+    builder.parse_block(text)
 }
 
-pub fn parse_logo_file(file: &str) -> AstNode {
-    let source = fs::read_to_string(file).unwrap_or_else(|err| {
-        eprintln!("{}", err);
-        process::exit(1);
-    });
-    parse_logo_source(&source).unwrap_or_else(|err| {
-        eprintln!("{}", err);
-        process::exit(1);
+/// Procedures that take their inputs unevaluated and are turned into dedicated nodes.
+fn special_form_arity(name: &str) -> Option<usize> {
+    match name {
+        "iftrue" | "ift" | "iffalse" | "iff" | "forever" => Some(1),
+        "if" | "repeat" | "while" | "until" | "do.while" | "do.until" | "for" => Some(2),
+        "ifelse" => Some(3),
+        _ => None,
+    }
+}
+
+/// Operators from the loosest to the tightest binding. `not` binds looser
+/// than comparisons and unary minus looser than `^`, the only operator that
+/// associates to the right.
+fn pratt_parser() -> &'static PrattParser<Rule> {
+    static PRATT_PARSER: OnceLock<PrattParser<Rule>> = OnceLock::new();
+    PRATT_PARSER.get_or_init(|| {
+        PrattParser::new()
+            .op(Op::infix(Rule::logic_op, Assoc::Left))
+            .op(Op::prefix(Rule::not_op))
+            .op(Op::infix(Rule::comp_op, Assoc::Left))
+            .op(Op::infix(Rule::add_op, Assoc::Left))
+            .op(Op::infix(Rule::mult_op, Assoc::Left))
+            .op(Op::prefix(Rule::neg_op))
+            .op(Op::infix(Rule::pow_op, Assoc::Right))
     })
 }
 
-pub fn parse_logo_source(source: &str) -> Result<AstNode, Error<Rule>> {
-    let mut pest_terms = LogoParser::parse(Rule::program, source)?;
-    let program = pest_terms.next().unwrap();
-    let eoi = pest_terms.next().unwrap();
-
-    assert!(pest_terms.next().is_none());
-    assert_eq!(program.as_rule(), Rule::block);
-    assert_eq!(eoi.as_rule(), Rule::EOI);
-
-    fn parse_term(term: Pair<Rule>) -> AstNode {
-        fn string_to_binop(s: &str) -> Binop {
-            match s {
-                "and" => Binop::And,
-                "or" => Binop::Or,
-                "<" => Binop::Less,
-                "<=" => Binop::LessEqual,
-                ">" => Binop::Greater,
-                ">=" => Binop::GreaterEqual,
-                "==" => Binop::EqualEqual,
-                "+" => Binop::Add,
-                "-" => Binop::Sub,
-                "*" => Binop::Mul,
-                "/" => Binop::Div,
-                _ => unreachable!(),
-            }
-        }
-
-        fn parse_binop(term: Pair<Rule>) -> AstNode {
-            let mut subterms = term.into_inner();
-            let lhs = subterms.next().unwrap();
-            let op = subterms.next();
-            if op.is_none() {
-                parse_term(lhs)
+fn string_to_binop(s: &str) -> Binop {
+    match s {
+        "and" => Binop::And,
+        "or" => Binop::Or,
+        "<" => Binop::Less,
+        "<=" => Binop::LessEqual,
+        ">" => Binop::Greater,
+        ">=" => Binop::GreaterEqual,
+        "=" | "==" => Binop::EqualEqual,
+        "<>" | "!=" => Binop::NotEqual,
+        "+" => Binop::Add,
+        "-" => Binop::Sub,
+        "*" => Binop::Mul,
+        "/" => Binop::Div,
+        "%" => Binop::Rem,
+        "^" => Binop::Pow,
+        _ => unreachable!(),
+    }
+}
+
+type Items<'i> = Peekable<std::vec::IntoIter<Pair<'i, Rule>>>;
+
+/// Turns the flat sequence of expressions produced by the grammar into calls,
+/// giving every procedure as many inputs as its arity requires.
+///
+/// Bracketed lists are kept as raw words by the grammar and are parsed again
+/// as blocks when they appear as the body of `if` or `repeat`; `offset` is the
+/// position of the text being parsed within `source`.
+///
+/// Comments may appear between any two tokens; they are set aside in
+/// `comments` and then placed in the innermost enclosing block.
+struct AstBuilder<'s> {
+    source: &'s str,
+    /// Offsets at which the lines of `source` begin.
+    lines: &'s [usize],
+    offset: usize,
+    /// Inputs taken by each known procedure, and the most it accepts in
+    /// parentheses.
+    arities: HashMap<String, (usize, usize)>,
+    comments: Vec<AstNode>,
+    /// Location given to every node instead of its own, for text that is not
+    /// part of the source.
+    location: Option<Span>,
+}
+
+impl<'s> AstBuilder<'s> {
+    fn span(&self, span: pest::Span) -> Span {
+        locate(self.source, self.lines, self.offset, self.location, span)
+    }
+
+    fn error(&self, span: pest::Span, message: String) -> Box<Error<Rule>> {
+        let span = pest::Span::new(
+            self.source,
+            self.offset + span.start(),
+            self.offset + span.end(),
+        )
+        .unwrap();
+        Box::new(Error::new_from_span(
+            ErrorVariant::CustomError { message },
+            span,
+        ))
+    }
+
+    fn relocate(&self, err: Error<Rule>) -> Box<Error<Rule>> {
+        let pos = match err.location {
+            InputLocation::Pos(pos) => pos,
+            InputLocation::Span((start, _)) => start,
+        };
+        let pos = Position::new(self.source, self.offset + pos).unwrap();
+        Box::new(Error::new_from_pos(err.variant, pos))
+    }
+
+    /// Returns the children of `term`, setting its comments aside.
+    fn inner<'i>(&mut self, term: Pair<'i, Rule>) -> Vec<Pair<'i, Rule>> {
+        self.without_comments(term.into_inner())
+    }
+
+    fn without_comments<'i>(
+        &mut self,
+        pairs: impl Iterator<Item = Pair<'i, Rule>>,
+    ) -> Vec<Pair<'i, Rule>> {
+        let mut children = vec![];
+        for t in pairs {
+            if t.as_rule() == Rule::COMMENT {
+                let span = self.span(t.as_span());
+                self.comments
+                    .push(AstNode::Comment(t.as_str()[1..].to_string(), span));
             } else {
-                let op = op.unwrap();
-                let rhs = subterms.next().unwrap();
-                AstNode::Binop {
-                    lhs: Box::new(parse_term(lhs)),
-                    op: string_to_binop(op.as_str()),
-                    rhs: Box::new(parse_term(rhs)),
-                }
+                children.push(t);
             }
         }
+        children
+    }
 
-        match term.as_rule() {
-            Rule::number => AstNode::Number(term.as_str().parse().unwrap()),
-            Rule::word => AstNode::String(term.as_str().to_string()),
-            Rule::string => AstNode::String(term.as_str()[1..].to_string()),
-            Rule::identifier | Rule::fn_identifier => AstNode::Variable(term.as_str().to_string()),
-            Rule::variable => AstNode::Variable(term.as_str()[1..].to_string()),
-            Rule::logic | Rule::comp | Rule::add | Rule::mult => parse_binop(term),
-            Rule::list => AstNode::List(term.into_inner().map(|t| parse_term(t)).collect()),
-            Rule::proc_call | Rule::fn_call => {
-                let mut ts = term.into_inner();
-                let f = Box::new(parse_term(ts.next().unwrap()));
-                let args: Vec<AstNode> = ts.map(|t| parse_term(t)).collect();
-                AstNode::Call { f, args }
-            }
-            Rule::cond => {
-                let mut ts = term.into_inner();
-                let condition = Box::new(parse_term(ts.next().unwrap()));
-                let body = Box::new(parse_term(ts.next().unwrap()));
-                AstNode::If { condition, body }
-            }
-            Rule::repeat => {
+    fn parse_block(&mut self, text: &str) -> Result<AstNode, Box<Error<Rule>>> {
+        let pest_terms =
+            LogoParser::parse(Rule::program, text).map_err(|err| self.relocate(err))?;
+        let mark = self.comments.len();
+        let mut pest_terms = self.without_comments(pest_terms).into_iter();
+        let program = pest_terms.next().unwrap();
+        let eoi = pest_terms.next().unwrap();
+
+        assert!(pest_terms.next().is_none());
+        assert_eq!(program.as_rule(), Rule::block);
+        assert_eq!(eoi.as_rule(), Rule::EOI);
+
+        self.build_block(program, mark)
+    }
+
+    /// Builds a block, which also receives the comments set aside since `mark`.
+    fn build_block(&mut self, block: Pair<Rule>, mark: usize) -> Result<AstNode, Box<Error<Rule>>> {
+        // Procedures are registered up front, so that they can be called
+        // recursively and before their definition.
+        for term in block.clone().into_inner() {
+            if term.as_rule() == Rule::proc_def {
                 let mut ts = term.into_inner();
-                let repeat = Box::new(parse_term(ts.next().unwrap()));
-                let body = Box::new(parse_term(ts.next().unwrap()));
-                AstNode::Loop { repeat, body }
+                let proc_name = ts.next().unwrap();
+                if special_form_arity(proc_name.as_str()).is_some() {
+                    return Err(self.error(
+                        proc_name.as_span(),
+                        format!("{} cannot be redefined", proc_name.as_str()),
+                    ));
+                }
+                let arity = ts.filter(|t| t.as_rule() == Rule::variable).count();
+                self.arities
+                    .insert(proc_name.as_str().to_string(), (arity, arity));
             }
+        }
+
+        // The block ends with its last statement, not with the whitespace after it.
+        let (input, start) = (block.get_input(), block.as_span().start());
+        let end = start + block.as_str().trim_end().len();
+        let span = self.span(pest::Span::new(input, start, end).unwrap());
+        let mut items = self.inner(block).into_iter().peekable();
+        let mut stmts = vec![];
+        while let Some(term) = items.next() {
+            stmts.push(self.build_statement(term, &mut items)?);
+        }
+        let comments = self.comments.split_off(mark);
+        if !comments.is_empty() {
+            stmts.extend(comments);
+            stmts.sort_by_key(|stmt| stmt.span().start);
+        }
+        Ok(AstNode::Block(stmts, span))
+    }
+
+    fn build_statement<'i>(
+        &mut self,
+        term: Pair<'i, Rule>,
+        rest: &mut Items<'i>,
+    ) -> Result<AstNode, Box<Error<Rule>>> {
+        match term.as_rule() {
             Rule::proc_def => {
-                let mut ts = term.into_inner();
+                let span = self.span(term.as_span());
+                // Comments in the title line or before `end` go to the body.
+                let mark = self.comments.len();
+                let mut ts = self.inner(term).into_iter();
                 let proc_name = ts.next().unwrap().as_str().to_string();
                 let mut rest: Vec<Pair<Rule>> = ts.collect();
                 let body = if let Some(b) = rest.pop() {
-                    Box::new(parse_term(b))
+                    Box::new(self.build_block(b, mark)?)
                 } else {
                     unreachable!("procedure should have a body!");
                 };
@@ -149,30 +389,494 @@ pub fn parse_logo_source(source: &str) -> Result<AstNode, Error<Rule>> {
                     .into_iter()
                     .map(|t| t.as_str()[1..].to_string())
                     .collect();
-                AstNode::ProcDef {
+                Ok(AstNode::ProcDef {
                     proc_name,
                     params,
                     body,
+                    span,
+                })
+            }
+            _ => self.build_expr(term, Some(rest)),
+        }
+    }
+
+    /// Builds an expression; only its rightmost operand may consume further
+    /// items from `rest` as the inputs of a procedure call.
+    fn build_expr<'i>(
+        &mut self,
+        term: Pair<'i, Rule>,
+        rest: Option<&mut Items<'i>>,
+    ) -> Result<AstNode, Box<Error<Rule>>> {
+        let span = self.span(term.as_span());
+        match term.as_rule() {
+            Rule::number => Ok(AstNode::Number(term.as_str().parse().unwrap(), span)),
+            Rule::string => Ok(AstNode::String(unescape(&term.as_str()[1..]), span)),
+            Rule::variable => Ok(AstNode::Variable(term.as_str()[1..].to_string(), span)),
+            Rule::slot => Ok(AstNode::Variable(term.as_str().to_string(), span)),
+            Rule::identifier => self.build_call(term, rest),
+            Rule::list => Ok(self.build_list(term)),
+            Rule::expr => {
+                let operands = self.inner(term);
+                let primaries = operands.iter().filter(|t| !is_operator(t)).count();
+                let (source, lines) = (self.source, self.lines);
+                let (offset, location) = (self.offset, self.location);
+                let mut rest = rest;
+                let mut seen = 0;
+                pratt_parser()
+                    .map_primary(|t| {
+                        seen += 1;
+                        if seen == primaries {
+                            self.build_expr(t, rest.take())
+                        } else {
+                            self.build_expr(t, None)
+                        }
+                    })
+                    .map_prefix(move |op, operand| {
+                        let operand = operand?;
+                        let span = locate(source, lines, offset, location, op.as_span())
+                            .to(operand.span());
+                        Ok(match (op.as_rule(), operand) {
+                            // Negative numbers are literals.
+                            (Rule::neg_op, AstNode::Number(n, _)) => AstNode::Number(-n, span),
+                            (rule, operand) => AstNode::Unop {
+                                op: match rule {
+                                    Rule::neg_op => Unop::Neg,
+                                    _ => Unop::Not,
+                                },
+                                operand: Box::new(operand),
+                                span,
+                            },
+                        })
+                    })
+                    .map_infix(|lhs, op, rhs| {
+                        let (lhs, rhs) = (lhs?, rhs?);
+                        Ok(AstNode::Binop {
+                            span: lhs.span().to(rhs.span()),
+                            lhs: Box::new(lhs),
+                            op: string_to_binop(op.as_str()),
+                            rhs: Box::new(rhs),
+                        })
+                    })
+                    .parse(operands.into_iter())
+            }
+            Rule::paren => {
+                let mut inner = self.inner(term).into_iter().peekable();
+                let first = inner.next().unwrap();
+                let expr = match self.variadic_head(&first) {
+                    Some(name) => self.build_variadic_call(name, &mut inner)?,
+                    None => self.build_expr(first, Some(&mut inner))?,
+                };
+                match inner.next() {
+                    Some(extra) => {
+                        Err(self.error(extra.as_span(), "too much inside parentheses".to_string()))
+                    }
+                    None => Ok(expr),
                 }
             }
-            Rule::block => AstNode::Block(term.into_inner().map(|t| parse_term(t)).collect()),
             Rule::WHITESPACE
+            | Rule::COMMENT
             | Rule::EOI
+            | Rule::word_char
+            | Rule::word
+            | Rule::escape
+            | Rule::bars
+            | Rule::string_char
             | Rule::keyword
-            | Rule::expr
             | Rule::statement
             | Rule::logic_op
             | Rule::comp_op
             | Rule::add_op
             | Rule::mult_op
+            | Rule::pow_op
+            | Rule::infix_op
+            | Rule::neg_op
+            | Rule::not_op
+            | Rule::prefix_op
+            | Rule::gap
+            | Rule::unary_minus
+            | Rule::operand
+            | Rule::list_word
             | Rule::primary
+            | Rule::proc_def
+            | Rule::block
             | Rule::program => {
                 unreachable!()
             }
         }
     }
 
-    Ok(parse_term(program))
+    /// Builds a list literal, whose elements are data: words that spell a
+    /// number become numbers and all others are kept as they are written.
+    fn build_list(&mut self, term: Pair<Rule>) -> AstNode {
+        let span = self.span(term.as_span());
+        AstNode::List(
+            self.inner(term)
+                .into_iter()
+                .map(|t| {
+                    let span = self.span(t.as_span());
+                    match t.as_rule() {
+                        Rule::list => self.build_list(t),
+                        _ => match number_word(t.as_str()) {
+                            Some(n) => AstNode::Number(n, span),
+                            None => AstNode::String(unescape(t.as_str()), span),
+                        },
+                    }
+                })
+                .collect(),
+            span,
+        )
+    }
+
+    fn build_call<'i>(
+        &mut self,
+        name: Pair<'i, Rule>,
+        mut rest: Option<&mut Items<'i>>,
+    ) -> Result<AstNode, Box<Error<Rule>>> {
+        let proc_name = name.as_str().to_string();
+        let name_span = self.span(name.as_span());
+        match &proc_name[..] {
+            "if" => {
+                let condition = Box::new(self.next_input(&name, rest.as_deref_mut())?);
+                let body = Box::new(self.next_body(&name, rest)?);
+                let span = name_span.to(body.span());
+                return Ok(AstNode::If {
+                    condition,
+                    body,
+                    else_body: None,
+                    span,
+                });
+            }
+            "ifelse" => {
+                let condition = Box::new(self.next_input(&name, rest.as_deref_mut())?);
+                let body = Box::new(self.next_body(&name, rest.as_deref_mut())?);
+                let else_body = Box::new(self.next_body(&name, rest)?);
+                let span = name_span.to(else_body.span());
+                return Ok(AstNode::If {
+                    condition,
+                    body,
+                    else_body: Some(else_body),
+                    span,
+                });
+            }
+            "iftrue" | "ift" | "iffalse" | "iff" => {
+                let body = Box::new(self.next_body(&name, rest)?);
+                let span = name_span.to(body.span());
+                return Ok(AstNode::IfTest {
+                    expected: matches!(&proc_name[..], "iftrue" | "ift"),
+                    body,
+                    span,
+                });
+            }
+            "repeat" => {
+                let repeat = Box::new(self.next_input(&name, rest.as_deref_mut())?);
+                let kind = LoopKind::Repeat(repeat);
+                let body = Box::new(self.next_body(&name, rest)?);
+                let span = name_span.to(body.span());
+                return Ok(AstNode::Loop { kind, body, span });
+            }
+            "while" | "until" => {
+                let condition = Box::new(self.next_body(&name, rest.as_deref_mut())?);
+                let kind = match &proc_name[..] {
+                    "while" => LoopKind::While(condition),
+                    _ => LoopKind::Until(condition),
+                };
+                let body = Box::new(self.next_body(&name, rest)?);
+                let span = name_span.to(body.span());
+                return Ok(AstNode::Loop { kind, body, span });
+            }
+            "do.while" | "do.until" => {
+                let body = Box::new(self.next_body(&name, rest.as_deref_mut())?);
+                let condition = Box::new(self.next_body(&name, rest)?);
+                let span = name_span.to(condition.span());
+                let kind = match &proc_name[..] {
+                    "do.while" => LoopKind::DoWhile(condition),
+                    _ => LoopKind::DoUntil(condition),
+                };
+                return Ok(AstNode::Loop { kind, body, span });
+            }
+            "for" => {
+                let kind = self.next_for_control(&name, rest.as_deref_mut())?;
+                let body = Box::new(self.next_body(&name, rest)?);
+                let span = name_span.to(body.span());
+                return Ok(AstNode::Loop { kind, body, span });
+            }
+            "forever" => {
+                let body = Box::new(self.next_body(&name, rest)?);
+                let span = name_span.to(body.span());
+                let kind = LoopKind::Forever;
+                return Ok(AstNode::Loop { kind, body, span });
+            }
+            _ => {}
+        }
+
+        let mut args = vec![];
+        match self.arities.get(&proc_name) {
+            Some(&(arity, _)) => {
+                for _ in 0..arity {
+                    args.push(self.next_input(&name, rest.as_deref_mut())?);
+                }
+            }
+            // The procedure may still be defined at run time, so it receives
+            // every following expression that does not start with a name.
+            None => {
+                if let Some(items) = rest {
+                    while let Some(t) =
+                        items.next_if(|t| t.as_rule() != Rule::proc_def && !starts_with_call(t))
+                    {
+                        args.push(self.build_expr(t, Some(items))?);
+                    }
+                }
+            }
+        }
+        let span = match args.last() {
+            Some(arg) => name_span.to(arg.span()),
+            None => name_span,
+        };
+        Ok(AstNode::Call {
+            f: Box::new(AstNode::Variable(proc_name, name_span)),
+            args,
+            span,
+        })
+    }
+
+    /// The procedure name that makes up `term`, if it is one that accepts more
+    /// inputs in parentheses than it takes otherwise.
+    fn variadic_head<'i>(&self, term: &Pair<'i, Rule>) -> Option<Pair<'i, Rule>> {
+        let mut subterms = term.clone().into_inner();
+        match (subterms.next(), subterms.next()) {
+            (Some(name), None) if name.as_rule() == Rule::identifier => {
+                match self.arities.get(name.as_str()) {
+                    Some((arity, max)) if max > arity => Some(name),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Builds a call that takes every input up to the closing parenthesis.
+    fn build_variadic_call<'i>(
+        &mut self,
+        name: Pair<'i, Rule>,
+        rest: &mut Items<'i>,
+    ) -> Result<AstNode, Box<Error<Rule>>> {
+        let name_span = self.span(name.as_span());
+        let max = self.arities[name.as_str()].1;
+        let mut args = vec![];
+        while let Some(t) = rest.next() {
+            if args.len() == max {
+                return Err(
+                    self.error(t.as_span(), format!("too many inputs to {}", name.as_str()))
+                );
+            }
+            args.push(self.build_expr(t, Some(rest))?);
+        }
+        let span = match args.last() {
+            Some(arg) => name_span.to(arg.span()),
+            None => name_span,
+        };
+        Ok(AstNode::Call {
+            f: Box::new(AstNode::Variable(name.as_str().to_string(), name_span)),
+            args,
+            span,
+        })
+    }
+
+    fn next_input<'i>(
+        &mut self,
+        caller: &Pair<'i, Rule>,
+        rest: Option<&mut Items<'i>>,
+    ) -> Result<AstNode, Box<Error<Rule>>> {
+        if let Some(items) = rest {
+            if let Some(t) = items.next_if(|t| t.as_rule() != Rule::proc_def) {
+                return self.build_expr(t, Some(items));
+            }
+        }
+        Err(self.error(
+            caller.as_span(),
+            format!("not enough inputs to {}", caller.as_str()),
+        ))
+    }
+
+    /// Unwraps an expression that consists of a single list literal.
+    fn literal_list<'i>(&mut self, term: Pair<'i, Rule>) -> Option<Pair<'i, Rule>> {
+        let mut subterms = self.inner(term).into_iter();
+        match (subterms.next(), subterms.next()) {
+            (Some(t), None) if t.as_rule() == Rule::list => Some(t),
+            _ => None,
+        }
+    }
+
+    fn next_list<'i>(
+        &mut self,
+        caller: &Pair<'i, Rule>,
+        rest: Option<&mut Items<'i>>,
+    ) -> Result<Pair<'i, Rule>, Box<Error<Rule>>> {
+        let list = rest
+            .and_then(|items| items.next())
+            .and_then(|t| self.literal_list(t));
+        list.ok_or_else(|| {
+            self.error(
+                caller.as_span(),
+                format!("{} expects a [ ... ] block", caller.as_str()),
+            )
+        })
+    }
+
+    /// Parses the part of `list` that starts at byte `from` of its text as a block.
+    fn parse_list_from(
+        &mut self,
+        list: &Pair<Rule>,
+        from: usize,
+    ) -> Result<Vec<AstNode>, Box<Error<Rule>>> {
+        let span = list.as_span();
+        let text = span.as_str();
+        let saved = self.offset;
+        self.offset += span.start() + from;
+        let block = self.parse_block(&text[from..text.len() - 1]);
+        self.offset = saved;
+        match block? {
+            AstNode::Block(stmts, _) => Ok(stmts),
+            _ => unreachable!(),
+        }
+    }
+
+    fn next_body<'i>(
+        &mut self,
+        caller: &Pair<'i, Rule>,
+        mut rest: Option<&mut Items<'i>>,
+    ) -> Result<AstNode, Box<Error<Rule>>> {
+        // A body that is not written out, such as a variable holding an
+        // instruction list, is given to `run` each time it is needed.
+        if let Some(items) = rest.as_deref_mut() {
+            if let Some(t) = items.next_if(|t| {
+                t.as_rule() == Rule::expr && !is_list_literal(t) && !starts_with_call(t)
+            }) {
+                let instructions = self.build_expr(t, Some(items))?;
+                let span = *instructions.span();
+                let run = AstNode::Call {
+                    f: Box::new(AstNode::Variable("run".to_string(), span)),
+                    args: vec![instructions],
+                    span,
+                };
+                return Ok(AstNode::Block(vec![run], span));
+            }
+        }
+        let list = self.next_list(caller, rest)?;
+        let stmts = self.parse_list_from(&list, 1)?;
+        // The body covers its brackets as well.
+        Ok(AstNode::Block(stmts, self.span(list.as_span())))
+    }
+
+    /// Parses the `[var start end step]` list of `for`; the step is optional.
+    fn next_for_control<'i>(
+        &mut self,
+        caller: &Pair<'i, Rule>,
+        rest: Option<&mut Items<'i>>,
+    ) -> Result<LoopKind, Box<Error<Rule>>> {
+        let list = self.next_list(caller, rest)?;
+        let var = list
+            .clone()
+            .into_inner()
+            .find(|t| t.as_rule() != Rule::COMMENT)
+            .filter(|t| t.as_rule() == Rule::list_word);
+        let message = format!("{} expects [variable start end step]", caller.as_str());
+        let Some(var) = var else {
+            return Err(self.error(list.as_span(), message));
+        };
+        let from = var.as_span().end() - list.as_span().start();
+        let mut values = self.parse_list_from(&list, from)?;
+        values.retain(|v| !matches!(v, AstNode::Comment(..)));
+        if !(2..=3).contains(&values.len()) {
+            return Err(self.error(list.as_span(), message));
+        }
+        let mut values = values.into_iter().map(Box::new);
+        Ok(LoopKind::For {
+            var: var.as_str().to_string(),
+            start: values.next().unwrap(),
+            end: values.next().unwrap(),
+            step: values.next(),
+        })
+    }
+}
+
+/// The characters of a word as written after a quote or in a list, without
+/// the backslashes and vertical bars that quote some of them.
+fn unescape(word: &str) -> String {
+    let mut text = String::new();
+    let mut chars = word.chars();
+    let mut in_bars = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if !in_bars => text.extend(chars.next()),
+            '|' if in_bars || chars.as_str().contains('|') => in_bars = !in_bars,
+            c => text.push(c),
+        }
+    }
+    text
+}
+
+/// The location of `span`, found at `offset` within `source`, unless all
+/// nodes share the same `location`.
+fn locate(
+    source: &str,
+    lines: &[usize],
+    offset: usize,
+    location: Option<Span>,
+    span: pest::Span,
+) -> Span {
+    location
+        .unwrap_or_else(|| Span::within(source, lines, offset + span.start(), offset + span.end()))
+}
+
+/// The offsets at which the lines of `source` begin.
+fn line_starts(source: &str) -> Vec<usize> {
+    let newlines = source.match_indices('\n').map(|(i, _)| i + 1);
+    std::iter::once(0).chain(newlines).collect()
+}
+
+/// The value of a word that is a number literal, possibly negative.
+pub fn number_word(word: &str) -> Option<f64> {
+    let digits = word.strip_prefix('-').unwrap_or(word);
+    let number = LogoParser::parse(Rule::number, digits).ok()?.next()?;
+    if number.as_str() == digits {
+        word.parse().ok()
+    } else {
+        None
+    }
+}
+
+fn is_operator(term: &Pair<Rule>) -> bool {
+    matches!(
+        term.as_rule(),
+        Rule::logic_op
+            | Rule::comp_op
+            | Rule::add_op
+            | Rule::mult_op
+            | Rule::pow_op
+            | Rule::neg_op
+            | Rule::not_op
+    )
+}
+
+fn is_list_literal(term: &Pair<Rule>) -> bool {
+    let mut subterms = term
+        .clone()
+        .into_inner()
+        .filter(|t| t.as_rule() != Rule::COMMENT);
+    match (subterms.next(), subterms.next()) {
+        (Some(t), None) => t.as_rule() == Rule::list,
+        _ => false,
+    }
+}
+
+fn starts_with_call(term: &Pair<Rule>) -> bool {
+    let first = term
+        .clone()
+        .into_inner()
+        .find(|t| t.as_rule() != Rule::COMMENT)
+        .unwrap();
+    first.as_rule() == Rule::identifier
 }
 
 #[cfg(test)]
@@ -185,16 +889,22 @@ mod tests {
         let ast = parse_logo_source(source);
         assert!(ast.is_ok());
         let ast = ast.unwrap();
+        let sp = |start, end| Span::new(source, start, end);
         assert_eq!(
             ast,
-            AstNode::Block(vec![AstNode::Call {
-                f: Box::new(AstNode::Variable("print".to_string())),
-                args: vec![AstNode::Binop {
-                    lhs: Box::new(AstNode::Number(1.0)),
-                    op: Binop::Add,
-                    rhs: Box::new(AstNode::Number(2.0))
-                }]
-            }])
+            AstNode::Block(
+                vec![AstNode::Call {
+                    f: Box::new(AstNode::Variable("print".to_string(), sp(0, 5))),
+                    args: vec![AstNode::Binop {
+                        lhs: Box::new(AstNode::Number(1.0, sp(6, 7))),
+                        op: Binop::Add,
+                        rhs: Box::new(AstNode::Number(2.0, sp(10, 11))),
+                        span: sp(6, 11),
+                    }],
+                    span: sp(0, 11),
+                }],
+                sp(0, 11)
+            )
         )
     }
 
@@ -204,20 +914,422 @@ mod tests {
         let ast = parse_logo_source(source);
         assert!(ast.is_ok());
         let ast = ast.unwrap();
+        let sp = |start, end| Span::new(source, start, end);
         assert_eq!(
             ast,
-            AstNode::Block(vec![AstNode::Call {
-                f: Box::new(AstNode::Variable("print".to_string())),
-                args: vec![AstNode::Binop {
-                    lhs: Box::new(AstNode::Number(1.0)),
-                    op: Binop::Add,
-                    rhs: Box::new(AstNode::Binop {
-                        lhs: Box::new(AstNode::Number(2.0)),
-                        op: Binop::Mul,
-                        rhs: Box::new(AstNode::Number(3.0)),
-                    })
-                }]
-            }])
+            AstNode::Block(
+                vec![AstNode::Call {
+                    f: Box::new(AstNode::Variable("print".to_string(), sp(0, 5))),
+                    args: vec![AstNode::Binop {
+                        lhs: Box::new(AstNode::Number(1.0, sp(6, 7))),
+                        op: Binop::Add,
+                        rhs: Box::new(AstNode::Binop {
+                            lhs: Box::new(AstNode::Number(2.0, sp(10, 11))),
+                            op: Binop::Mul,
+                            rhs: Box::new(AstNode::Number(3.0, sp(14, 15))),
+                            span: sp(10, 15),
+                        }),
+                        span: sp(6, 15),
+                    }],
+                    span: sp(0, 15),
+                }],
+                sp(0, 15)
+            )
+        )
+    }
+
+    #[test]
+    fn call_arity() {
+        let source = "to half :x output :x / 2 end fd half 10 rt 90";
+        let ast = parse_logo_source(source);
+        assert!(ast.is_ok());
+        let ast = ast.unwrap();
+        let sp = |start, end| Span::new(source, start, end);
+        let call = |name: &str, start, end, args: Vec<AstNode>| AstNode::Call {
+            f: Box::new(AstNode::Variable(
+                name.to_string(),
+                sp(start, start + name.len()),
+            )),
+            args,
+            span: sp(start, end),
+        };
+        assert_eq!(
+            ast,
+            AstNode::Block(
+                vec![
+                    AstNode::ProcDef {
+                        proc_name: "half".to_string(),
+                        params: vec!["x".to_string()],
+                        body: Box::new(AstNode::Block(
+                            vec![call(
+                                "output",
+                                11,
+                                24,
+                                vec![AstNode::Binop {
+                                    lhs: Box::new(AstNode::Variable("x".to_string(), sp(18, 20))),
+                                    op: Binop::Div,
+                                    rhs: Box::new(AstNode::Number(2.0, sp(23, 24))),
+                                    span: sp(18, 24),
+                                }]
+                            )],
+                            sp(11, 24)
+                        )),
+                        span: sp(0, 28),
+                    },
+                    call(
+                        "fd",
+                        29,
+                        39,
+                        vec![call(
+                            "half",
+                            32,
+                            39,
+                            vec![AstNode::Number(10.0, sp(37, 39))]
+                        )]
+                    ),
+                    call("rt", 40, 45, vec![AstNode::Number(90.0, sp(43, 45))]),
+                ],
+                sp(0, 45)
+            )
         )
     }
+
+    #[test]
+    fn not_enough_inputs() {
+        assert!(parse_logo_source("fd").is_err());
+        assert!(parse_logo_source("fd random + 1").is_err());
+        assert!(parse_logo_source("if 1 < 2 fd 10").is_err());
+    }
+
+    #[test]
+    fn spans_in_nested_blocks() {
+        let source = "repeat 2 [\n  fd 10\n]";
+        let ast = parse_logo_source(source).unwrap();
+        let AstNode::Block(stmts, _) = ast else {
+            panic!("expected a block")
+        };
+        let AstNode::Loop { body, span, .. } = &stmts[0] else {
+            panic!("expected a loop")
+        };
+        assert_eq!((span.start, span.end), (0, 20));
+        assert_eq!((body.span().line, body.span().col), (1, 10));
+        let AstNode::Block(stmts, _) = &**body else {
+            panic!("expected a block")
+        };
+        assert_eq!(stmts[0].span(), &Span::new(source, 13, 18));
+        assert_eq!((stmts[0].span().line, stmts[0].span().col), (2, 3));
+    }
+
+    /// Renders the expression of a single-statement program with explicit parentheses.
+    fn grouping(source: &str) -> String {
+        fn render(node: &AstNode) -> String {
+            match node {
+                AstNode::Number(n, _) => n.to_string(),
+                AstNode::Variable(x, _) => format!(":{}", x),
+                AstNode::Unop { op, operand, .. } => format!("({:?} {})", op, render(operand)),
+                AstNode::Binop { lhs, op, rhs, .. } => {
+                    format!("({} {:?} {})", render(lhs), op, render(rhs))
+                }
+                AstNode::Call { f, args, .. } => {
+                    let args: Vec<String> = args.iter().map(render).collect();
+                    format!("({} {})", render(f).trim_start_matches(':'), args.join(" "))
+                }
+                _ => panic!("unexpected node in grouping: {:?}", node),
+            }
+        }
+        match parse_logo_source(source).unwrap() {
+            AstNode::Block(stmts, _) => stmts
+                .iter()
+                .filter(|stmt| !matches!(stmt, AstNode::Comment(..)))
+                .map(render)
+                .collect::<Vec<_>>()
+                .join(" "),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn left_associativity() {
+        assert_eq!(grouping("fd 10 - 2 - 3"), "(fd ((10 Sub 2) Sub 3))");
+        assert_eq!(grouping("fd 8 / 4 / 2"), "(fd ((8 Div 4) Div 2))");
+        assert_eq!(grouping("fd 8 / 4 * 2"), "(fd ((8 Div 4) Mul 2))");
+        assert_eq!(grouping("fd 1 - 2 + 3"), "(fd ((1 Sub 2) Add 3))");
+    }
+
+    #[test]
+    fn mixed_operator_chains() {
+        assert_eq!(
+            grouping("fd 1 + 2 * 3 - 4 / 2"),
+            "(fd ((1 Add (2 Mul 3)) Sub (4 Div 2)))"
+        );
+        assert_eq!(
+            grouping("fd :a - :b * :c < :d and :e == 1 or 2 > 1"),
+            "(fd ((((:a Sub (:b Mul :c)) Less :d) And (:e EqualEqual 1)) Or (2 Greater 1)))"
+        );
+        assert_eq!(
+            grouping("fd (1 - 2) - (3 - 4) * 5"),
+            "(fd ((1 Sub 2) Sub ((3 Sub 4) Mul 5)))"
+        );
+        assert_eq!(
+            grouping("fd 2 * random 4 - 1 rt 90"),
+            "(fd (2 Mul (random (4 Sub 1)))) (rt 90)"
+        );
+    }
+
+    #[test]
+    fn comparison_operators() {
+        assert_eq!(grouping("fd :x <= 3"), "(fd (:x LessEqual 3))");
+        assert_eq!(grouping("fd :x >= 3"), "(fd (:x GreaterEqual 3))");
+        assert_eq!(grouping("fd :x < 3"), "(fd (:x Less 3))");
+        assert_eq!(grouping("fd :x = 3"), "(fd (:x EqualEqual 3))");
+        assert_eq!(grouping("fd :x == 3"), "(fd (:x EqualEqual 3))");
+        assert_eq!(grouping("fd :x <> 3"), "(fd (:x NotEqual 3))");
+        assert_eq!(grouping("fd :x != 3"), "(fd (:x NotEqual 3))");
+        assert_eq!(
+            grouping("fd 1 + 1 = 2 and :x <= 3"),
+            "(fd (((1 Add 1) EqualEqual 2) And (:x LessEqual 3)))"
+        );
+    }
+
+    #[test]
+    fn unary_operators() {
+        assert_eq!(grouping("fd -:x"), "(fd (Neg :x))");
+        assert_eq!(grouping("fd -3"), "(fd -3)");
+        assert_eq!(grouping("fd 2 * - :x + 1"), "(fd ((2 Mul (Neg :x)) Add 1))");
+        assert_eq!(grouping("fd -2 ^ 2"), "(fd (Neg (2 Pow 2)))");
+        assert_eq!(grouping("fd 2 ^ 3 ^ 2"), "(fd (2 Pow (3 Pow 2)))");
+        assert_eq!(grouping("fd 7 % 3 * 2"), "(fd ((7 Rem 3) Mul 2))");
+        assert_eq!(
+            grouping("fd not :a = 1 and not :b"),
+            "(fd ((Not (:a EqualEqual 1)) And (Not :b)))"
+        );
+        assert_eq!(grouping("fd notable"), "(fd (notable ))");
+        // A minus sign with a space only before it starts a new expression.
+        assert_eq!(grouping("fd 10 -2"), "(fd 10) -2");
+        assert_eq!(grouping("fd 10 - 2"), "(fd (10 Sub 2))");
+        assert_eq!(grouping("fd 10-2"), "(fd (10 Sub 2))");
+        assert_eq!(grouping("fd :x -(1 + 2)"), "(fd :x) (Neg (1 Add 2))");
+    }
+
+    #[test]
+    fn parenthesized_inputs() {
+        assert_eq!(grouping("fd (max 1 2 3)"), "(fd (max 1 2 3))");
+        assert_eq!(
+            grouping("fd (arctan 1 :x + 1)"),
+            "(fd (arctan 1 (:x Add 1)))"
+        );
+        assert_eq!(grouping("fd (max 1 random 3)"), "(fd (max 1 (random 3)))");
+        assert_eq!(grouping("fd max 1 2 rt 3"), "(fd (max 1 2)) (rt 3)");
+        assert!(parse_logo_source("fd (arctan 1 2 3)").is_err());
+        assert!(parse_logo_source("fd (sin 1 2)").is_err());
+    }
+
+    #[test]
+    fn list_literals() {
+        let source = "make \"x [1 -2.5 [a 3] :x \"y 1e5 - 1. red]";
+        let AstNode::Block(stmts, _) = parse_logo_source(source).unwrap() else {
+            panic!("expected a block")
+        };
+        let AstNode::Call { args, .. } = &stmts[0] else {
+            panic!("expected a call")
+        };
+        let sp = |start, end| Span::new(source, start, end);
+        let word = |w: &str, start| AstNode::String(w.to_string(), sp(start, start + w.len()));
+        assert_eq!(
+            args[1],
+            AstNode::List(
+                vec![
+                    AstNode::Number(1.0, sp(9, 10)),
+                    AstNode::Number(-2.5, sp(11, 15)),
+                    AstNode::List(
+                        vec![word("a", 17), AstNode::Number(3.0, sp(19, 20))],
+                        sp(16, 21)
+                    ),
+                    word(":x", 22),
+                    word("\"y", 25),
+                    word("1e5", 28),
+                    word("-", 32),
+                    word("1.", 34),
+                    word("red", 37),
+                ],
+                sp(8, 41)
+            )
+        );
+    }
+
+    #[test]
+    fn quoted_words() {
+        let source = r#"(list "Hello,\ world "|a [b]; c| "1st "a\|b "|x|\\y " [|p q| r\]s])"#;
+        let AstNode::Block(stmts, _) = parse_logo_source(source).unwrap() else {
+            panic!("expected a block")
+        };
+        let AstNode::Call { args, .. } = &stmts[0] else {
+            panic!("expected a call")
+        };
+        let words = |nodes: &[AstNode]| -> Vec<String> {
+            nodes
+                .iter()
+                .map(|node| match node {
+                    AstNode::String(w, _) => w.clone(),
+                    _ => panic!("expected a word"),
+                })
+                .collect()
+        };
+        assert_eq!(
+            words(&args[..6]),
+            ["Hello, world", "a [b]; c", "1st", "a|b", "x\\y", ""]
+        );
+        let AstNode::List(elems, _) = &args[6] else {
+            panic!("expected a list")
+        };
+        assert_eq!(words(elems), ["p q", "r]s"]);
+    }
+
+    #[test]
+    fn identifiers() {
+        for name in [
+            "total",
+            "ending",
+            "iffy",
+            "repeatedly",
+            "andy",
+            "square2",
+            "draw-tree",
+            "step_size",
+            "empty?",
+            "do.it",
+            "żółw",
+        ] {
+            let source = format!("to {} :{} fd :{} end {} 1", name, name, name, name);
+            assert_eq!(
+                grouping(&format!("{} 1", name)),
+                format!("({} 1)", name),
+                "{}",
+                name
+            );
+            assert!(parse_logo_source(&source).is_ok(), "{}", source);
+        }
+        assert!(parse_logo_source("to to end").is_err());
+        assert!(parse_logo_source("fd :end").is_err());
+        assert_eq!(grouping("fd :a-1"), "(fd :a-1)");
+        assert_eq!(grouping("fd :a - 1"), "(fd (:a Sub 1))");
+    }
+
+    #[test]
+    fn conditionals() {
+        let source = "ifelse :x [fd 1] [fd 2] test :x iftrue [rt 90]";
+        let AstNode::Block(stmts, _) = parse_logo_source(source).unwrap() else {
+            panic!("expected a block")
+        };
+        let AstNode::If {
+            else_body, span, ..
+        } = &stmts[0]
+        else {
+            panic!("expected a conditional")
+        };
+        assert_eq!(
+            else_body.as_ref().unwrap().span(),
+            &Span::new(source, 17, 23)
+        );
+        assert_eq!(span, &Span::new(source, 0, 23));
+        let AstNode::IfTest { expected, .. } = &stmts[2] else {
+            panic!("expected iftrue")
+        };
+        assert!(expected);
+
+        assert!(parse_logo_source("ifelse :x [fd 1]").is_err());
+        assert!(parse_logo_source("ifelse :x [fd 1] fd 2").is_err());
+        assert!(parse_logo_source("iffalse fd 2").is_err());
+        assert!(parse_logo_source("to ifelse end").is_err());
+    }
+
+    #[test]
+    fn loops() {
+        let source = "for [i 1 :n - 1] [fd :i] do.while [fd 1] [:x]";
+        let AstNode::Block(stmts, _) = parse_logo_source(source).unwrap() else {
+            panic!("expected a block")
+        };
+        let AstNode::Loop {
+            kind:
+                LoopKind::For {
+                    var,
+                    start,
+                    end,
+                    step,
+                },
+            ..
+        } = &stmts[0]
+        else {
+            panic!("expected a for loop")
+        };
+        assert_eq!(var, "i");
+        assert_eq!(**start, AstNode::Number(1.0, Span::new(source, 7, 8)));
+        assert_eq!(end.span(), &Span::new(source, 9, 15));
+        assert_eq!(step, &None);
+        let AstNode::Loop {
+            kind: LoopKind::DoWhile(condition),
+            span,
+            ..
+        } = &stmts[1]
+        else {
+            panic!("expected a do.while loop")
+        };
+        assert_eq!(condition.span(), &Span::new(source, 41, 45));
+        assert_eq!(span, &Span::new(source, 25, 45));
+
+        assert!(parse_logo_source("for [i 1] [fd :i]").is_err());
+        assert!(parse_logo_source("for [i 1 2 3 4] [fd :i]").is_err());
+        assert!(parse_logo_source("for [[i] 1 2] [fd :i]").is_err());
+        assert!(parse_logo_source("while fd 1 [fd 1]").is_err());
+        assert!(parse_logo_source("forever").is_err());
+    }
+
+    #[test]
+    fn comments() {
+        let source =
+            "; start\nto f :x ; title\n  fd :x\n  ; last\nend\nrepeat 2 [f 1 ; body\n]; end";
+        let ast = parse_logo_source(source).unwrap();
+        let comment = |text: &str| {
+            let start = source.find(text).unwrap() - 1;
+            AstNode::Comment(
+                text.to_string(),
+                Span::new(source, start, start + text.len() + 1),
+            )
+        };
+        let AstNode::Block(stmts, _) = ast else {
+            panic!("expected a block")
+        };
+        assert_eq!(stmts.len(), 4);
+        assert_eq!(stmts[0], comment(" start"));
+        assert_eq!(stmts[3], comment(" end"));
+        let AstNode::ProcDef { body, .. } = &stmts[1] else {
+            panic!("expected a procedure")
+        };
+        let AstNode::Block(body, _) = &**body else {
+            panic!("expected a block")
+        };
+        assert_eq!(body[0], comment(" title"));
+        assert_eq!(body[2], comment(" last"));
+        assert_eq!((body[2].span().line, body[2].span().col), (4, 3));
+        let AstNode::Loop { body, .. } = &stmts[2] else {
+            panic!("expected a loop")
+        };
+        let AstNode::Block(body, _) = &**body else {
+            panic!("expected a block")
+        };
+        assert_eq!(body[1], comment(" body"));
+
+        assert_eq!(grouping("fd 1 ; + 2\n+ 3"), "(fd (1 Add 3))");
+    }
+
+    #[test]
+    fn span_lines() {
+        let source = "fd 1\n\nlabel \"été rt 90\n  fd 2\n";
+        let lines = line_starts(source);
+        for (start, _) in source.char_indices() {
+            let span = Span::within(source, &lines, start, start);
+            let expected = Position::new(source, start).unwrap().line_col();
+            assert_eq!((span.line, span.col), expected, "{}", start);
+        }
+    }
 }
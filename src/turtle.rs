@@ -0,0 +1,195 @@
+use super::drawer::DrawCmd;
+use std::collections::HashMap;
+
+/// Position and pen of a turtle. The origin is at the center of the image and
+/// `y` grows upwards; the heading is in degrees, clockwise from north.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Turtle {
+    pub x: f64,
+    pub y: f64,
+    pub heading: f64,
+    pub pendown: bool,
+    pub color: String,
+    /// Color of the shapes filled with `beginfill` and `endfill`.
+    pub fill_color: String,
+    pub font_size: f64,
+}
+
+impl Default for Turtle {
+    fn default() -> Self {
+        Self {
+            x: 0.0,
+            y: 0.0,
+            heading: 0.0,
+            pendown: true,
+            color: "black".to_string(),
+            fill_color: "black".to_string(),
+            font_size: 12.0,
+        }
+    }
+}
+
+impl Turtle {
+    /// Moves `distance` along the heading, or backwards if it is negative.
+    pub fn forward(&mut self, distance: f64) {
+        let (sin, cos) = sin_cos(self.heading);
+        self.x += distance * sin;
+        self.y += distance * cos;
+    }
+
+    /// Turns clockwise by `degrees`, or anticlockwise if it is negative.
+    pub fn turn(&mut self, degrees: f64) {
+        self.heading = normalize(self.heading + degrees);
+    }
+
+    /// The heading that points from the turtle towards `(x, y)`.
+    pub fn towards(&self, x: f64, y: f64) -> f64 {
+        normalize((x - self.x).atan2(y - self.y).to_degrees())
+    }
+
+    pub fn distance(&self, x: f64, y: f64) -> f64 {
+        (x - self.x).hypot(y - self.y)
+    }
+
+    /// The arc drawn by `arc`, centered on the turtle and starting in the
+    /// direction it is heading.
+    pub fn arc(&self, angle: f64, radius: f64) -> Arc {
+        Arc {
+            center: (self.x, self.y),
+            radius,
+            start: self.heading,
+            angle,
+        }
+    }
+
+    /// The arc that the turtle follows with `arc2`: it starts where the turtle
+    /// is, in the direction it is heading, and bends to the right when `angle`
+    /// is positive or to the left otherwise.
+    pub fn arc2(&self, angle: f64, radius: f64) -> Arc {
+        let side = if angle < 0.0 { -90.0 } else { 90.0 };
+        let (sin, cos) = sin_cos(self.heading + side);
+        Arc {
+            center: (self.x + radius * sin, self.y + radius * cos),
+            radius,
+            start: self.heading - side,
+            angle,
+        }
+    }
+}
+
+/// Part of a circle, going `angle` degrees clockwise around `center` from the
+/// point in the direction `start`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Arc {
+    pub center: (f64, f64),
+    pub radius: f64,
+    start: f64,
+    pub angle: f64,
+}
+
+impl Arc {
+    /// The point reached after going `angle` degrees along the arc.
+    pub fn at(&self, angle: f64) -> (f64, f64) {
+        let (sin, cos) = sin_cos(self.start + angle);
+        let (x, y) = self.center;
+        (x + self.radius * sin, y + self.radius * cos)
+    }
+
+    /// Where the arc ends, which only depends on `angle` modulo 360.
+    pub fn end(&self) -> (f64, f64) {
+        self.at(self.angle % 360.0)
+    }
+
+    /// The same arc without the turns it makes beyond a full circle and what
+    /// is left to reach its end, as they only go over the circle again.
+    pub fn shortened(&self) -> Arc {
+        let mut arc = self.clone();
+        if arc.angle.abs() > 360.0 {
+            arc.angle = arc.angle % 360.0 + 360.0_f64.copysign(arc.angle);
+        }
+        arc
+    }
+}
+
+/// The same angle between 0 and 360 degrees. Adding zero turns -0 into 0.
+fn normalize(degrees: f64) -> f64 {
+    degrees.rem_euclid(360.0) + 0.0
+}
+
+/// Sine and cosine of an angle in degrees, exact for multiples of 90 so that
+/// the turtle stays on whole coordinates when it only turns by right angles.
+fn sin_cos(degrees: f64) -> (f64, f64) {
+    match degrees.rem_euclid(360.0) {
+        0.0 => (0.0, 1.0),
+        90.0 => (1.0, 0.0),
+        180.0 => (0.0, -1.0),
+        270.0 => (-1.0, 0.0),
+        d => d.to_radians().sin_cos(),
+    }
+}
+
+/// Every turtle created by `setturtle`, and the one that commands apply to.
+pub struct Turtles {
+    turtles: HashMap<i32, Turtle>,
+    current: i32,
+}
+
+impl Default for Turtles {
+    fn default() -> Self {
+        Self {
+            turtles: HashMap::from([(1, Turtle::default())]),
+            current: 1,
+        }
+    }
+}
+
+impl Turtles {
+    pub fn current(&self) -> &Turtle {
+        &self.turtles[&self.current]
+    }
+
+    fn current_mut(&mut self) -> &mut Turtle {
+        self.turtles.get_mut(&self.current).unwrap()
+    }
+
+    /// Updates the turtles as `cmd` requires. The interpreter and the drawer
+    /// both go through here, so that they agree on where each turtle is.
+    pub fn apply(&mut self, cmd: &DrawCmd) {
+        match cmd {
+            DrawCmd::Forward(d) => self.current_mut().forward(*d),
+            DrawCmd::Back(d) => self.current_mut().forward(-d),
+            DrawCmd::LeftTurn(d) => self.current_mut().turn(-d),
+            DrawCmd::RightTurn(d) => self.current_mut().turn(*d),
+            DrawCmd::SetColor(c) => self.current_mut().color = c.clone(),
+            DrawCmd::SetFillColor(c) => self.current_mut().fill_color = c.clone(),
+            DrawCmd::PenUp => self.current_mut().pendown = false,
+            DrawCmd::PenDown => self.current_mut().pendown = true,
+            DrawCmd::SetFontSize(n) => self.current_mut().font_size = *n,
+            DrawCmd::SetTurtle(idx) => {
+                self.turtles.entry(*idx).or_default();
+                self.current = *idx;
+            }
+            DrawCmd::SetXY(x, y) => {
+                let turtle = self.current_mut();
+                (turtle.x, turtle.y) = (*x, *y);
+            }
+            DrawCmd::SetX(x) => self.current_mut().x = *x,
+            DrawCmd::SetY(y) => self.current_mut().y = *y,
+            DrawCmd::SetHeading(h) => self.current_mut().heading = normalize(*h),
+            DrawCmd::Home => {
+                let turtle = self.current_mut();
+                (turtle.x, turtle.y, turtle.heading) = (0.0, 0.0, 0.0);
+            }
+            DrawCmd::Arc2(angle, radius) => {
+                let turtle = self.current_mut();
+                (turtle.x, turtle.y) = turtle.arc2(*angle, *radius).end();
+                turtle.turn(*angle);
+            }
+            DrawCmd::ClearScreen
+            | DrawCmd::Label(_)
+            | DrawCmd::Arc(..)
+            | DrawCmd::BeginFill
+            | DrawCmd::EndFill => {}
+        }
+    }
+}